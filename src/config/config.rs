@@ -1,39 +1,642 @@
+use std::{ net::SocketAddr, path::Path, sync::Arc };
+
+use axum::http::HeaderValue;
+use lettre::message::Mailbox;
+use mongodb::bson::DateTime;
+
+use crate::utils::breached_passwords::BreachedPasswords;
+use super::secret::{ Secret, SecretSource };
+use super::source::{ ConfigError, ConfigSource };
+
+// Every setting of the service, read once at startup. See `ConfigSource` for where they come
+// from. Passwords, keys and URLs that may embed credentials are `Secret`s, so printing a config
+// with `{:?}` doesn't leak them.
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub google_oauth_client_id: String,
-    pub google_oauth_client_secret: String,
-    pub google_oauth_redirect_url: String,
-    pub google_smtp_username: String,
-    pub google_smtp_password: String,
-    pub client_url: String,
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub session: SessionConfig,
+    pub account_deletion: AccountDeletionConfig,
+    pub verification_code: VerificationCodeConfig,
+    pub email: EmailConfig,
+    pub password_policy: PasswordPolicy,
+    pub password_hash: PasswordHashConfig,
+    pub login_protection: LoginProtectionConfig,
+    pub rate_limit: RateLimitConfig,
+    pub google: Option<GoogleConfig>,
+    pub smtp: Option<SmtpConfig>,
 }
 
 impl Config {
-    pub fn init() -> Config {
-        let google_oauth_client_id = std::env
-            ::var("GOOGLE_OAUTH_CLIENT_ID")
-            .expect("GOOGLE_OAUTH_CLIENT_ID must be set");
-        let google_oauth_client_secret = std::env
-            ::var("GOOGLE_OAUTH_CLIENT_SECRET")
-            .expect("GOOGLE_OAUTH_CLIENT_SECRET must be set");
-        let google_oauth_redirect_url = std::env
-            ::var("GOOGLE_OAUTH_REDIRECT_URL")
-            .expect("GOOGLE_OAUTH_REDIRECT_URL must be set");
-        let client_url = std::env::var("CLIENT_URL").expect("CLIENT_URL must be set");
-        let google_smtp_password = std::env
-            ::var("GOOGLE_SMTP_PASSWORD")
-            .expect("GOOGLE_SMTP_PASSWORD must be set");
-        let google_smtp_username = std::env
-            ::var("GOOGLE_SMTP_USERNAME")
-            .expect("GOOGLE_SMTP_USERNAME must be set");
+    // Reports every invalid or missing setting at once instead of stopping at the first.
+    pub fn load() -> Result<Config, ConfigError> {
+        Config::load_with(vec![])
+    }
+
+    // Also looks secrets up in `secret_sources`, after `KEY_FILE` files and SECRETS_FILE.
+    pub fn load_with(secret_sources: Vec<Box<dyn SecretSource>>) -> Result<Config, ConfigError> {
+        let source = ConfigSource::load(secret_sources)?;
+        let config = Config::init(&source);
+        source.finish()?;
+        Ok(config)
+    }
 
+    pub fn init(source: &ConfigSource) -> Config {
         Config {
-            google_oauth_client_id,
-            google_oauth_client_secret,
-            google_oauth_redirect_url,
-            client_url,
-            google_smtp_password,
-            google_smtp_username,
+            server: ServerConfig::init(source),
+            database: DatabaseConfig::init(source),
+            session: SessionConfig::init(source),
+            account_deletion: AccountDeletionConfig::init(source),
+            verification_code: VerificationCodeConfig::init(source),
+            email: EmailConfig::init(source),
+            password_policy: PasswordPolicy::init(source),
+            password_hash: PasswordHashConfig::init(source),
+            login_protection: LoginProtectionConfig::init(source),
+            rate_limit: RateLimitConfig::init(source),
+            google: GoogleConfig::init(source),
+            smtp: SmtpConfig::init(source),
+        }
+    }
+}
+
+// Google sign-in. Enabled by setting any GOOGLE_OAUTH_* value, which then requires all of them.
+#[derive(Debug, Clone)]
+pub struct GoogleConfig {
+    pub oauth_client_id: String,
+    pub oauth_client_secret: Secret,
+    pub oauth_redirect_url: String,
+    // Frontend the user is sent back to after signing in.
+    pub client_url: String,
+}
+
+impl GoogleConfig {
+    pub fn init(source: &ConfigSource) -> Option<GoogleConfig> {
+        let keys = [
+            "GOOGLE_OAUTH_CLIENT_ID",
+            "GOOGLE_OAUTH_CLIENT_SECRET",
+            "GOOGLE_OAUTH_REDIRECT_URL",
+        ];
+        if !source.any_set(&keys) {
+            return None;
+        }
+        Some(GoogleConfig {
+            oauth_client_id: source.require("GOOGLE_OAUTH_CLIENT_ID"),
+            oauth_client_secret: source.require_secret("GOOGLE_OAUTH_CLIENT_SECRET"),
+            oauth_redirect_url: source.require("GOOGLE_OAUTH_REDIRECT_URL"),
+            client_url: source.require("CLIENT_URL"),
+        })
+    }
+}
+
+// Outgoing email, used for verification codes and account notices. Enabled by setting the SMTP
+// username; without it those emails are skipped. The sender defaults to the username.
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub username: String,
+    pub password: Secret,
+    pub from: Mailbox,
+    pub reply_to: Option<Mailbox>,
+}
+
+impl SmtpConfig {
+    pub fn init(source: &ConfigSource) -> Option<SmtpConfig> {
+        // GOOGLE_SMTP_* are the names from before other providers were supported.
+        let username = source.get("SMTP_USERNAME").or_else(|| source.get("GOOGLE_SMTP_USERNAME"));
+        let password = source
+            .secret("SMTP_PASSWORD")
+            .or_else(|| source.secret("GOOGLE_SMTP_PASSWORD"));
+        let username = match (username, password.is_some()) {
+            (Some(username), _) => username,
+            (None, true) => {
+                source.error("SMTP_USERNAME must be set when SMTP_PASSWORD is".to_string());
+                return None;
+            }
+            (None, false) => {
+                return None;
+            }
+        };
+        let mailbox = |key: &str, value: String| {
+            value
+                .parse::<Mailbox>()
+                .map_err(|err| source.error(format!("{} is not a valid address: {}", key, err)))
+                .ok()
+        };
+
+        // The error is recorded, so `finish` fails and the missing section doesn't matter.
+        let from = mailbox("SMTP_FROM", source.get("SMTP_FROM").unwrap_or(username.clone()))?;
+
+        Some(SmtpConfig {
+            host: source.get("SMTP_HOST").unwrap_or_else(|| "smtp.gmail.com".to_string()),
+            from,
+            reply_to: source.get("SMTP_REPLY_TO").and_then(|value| mailbox("SMTP_REPLY_TO", value)),
+            password: password.unwrap_or_else(|| source.require_secret("SMTP_PASSWORD")),
+            username,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum DatabaseBackend {
+    Mongo,
+    Memory,
+    Sql,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub backend: DatabaseBackend,
+    // May hold the database password.
+    pub url: Secret,
+    // Database to use on the Mongo server.
+    pub name: String,
+}
+
+impl DatabaseConfig {
+    pub fn init(source: &ConfigSource) -> DatabaseConfig {
+        let backend = source.get("DATABASE_BACKEND").unwrap_or_else(|| "mongo".to_string());
+        let name = source.get("DATABASE_NAME").unwrap_or_else(|| "rustDB".to_string());
+
+        match backend.to_lowercase().as_str() {
+            "mongo" => {
+                let url = source.require_secret("MONGO_URI");
+                DatabaseConfig { backend: DatabaseBackend::Mongo, url, name }
+            }
+            "memory" =>
+                DatabaseConfig { backend: DatabaseBackend::Memory, url: Secret::default(), name },
+            "sql" => {
+                let url = source.require_secret("DATABASE_URL");
+                DatabaseConfig { backend: DatabaseBackend::Sql, url, name }
+            }
+            other => {
+                source.error(format!("Unknown DATABASE_BACKEND: {}", other));
+                DatabaseConfig { backend: DatabaseBackend::Memory, url: Secret::default(), name }
+            }
+        }
+    }
+}
+
+// Small built-in list of throwaway inbox providers. Extend it with EMAIL_DISPOSABLE_DOMAINS_FILE.
+const DISPOSABLE_DOMAINS: [&str; 14] = [
+    "10minutemail.com",
+    "dispostable.com",
+    "fakeinbox.com",
+    "getnada.com",
+    "guerrillamail.com",
+    "mailinator.com",
+    "maildrop.cc",
+    "sharklasers.com",
+    "temp-mail.org",
+    "tempmail.com",
+    "throwawaymail.com",
+    "trashmail.com",
+    "yopmail.com",
+    "mailnesia.com",
+];
+
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    // Treat the local part as case-insensitive. RFC 5321 allows case-sensitive mailboxes, but
+    // practically no provider uses them.
+    pub lowercase_local_part: bool,
+    // When not empty, only these domains (and their subdomains) may register.
+    pub allowed_domains: Vec<String>,
+    pub denied_domains: Vec<String>,
+    pub reject_disposable: bool,
+    pub disposable_domains: Vec<String>,
+}
+
+// Comma separated list of domains, stored in the same ASCII form `Email::normalize` produces.
+fn domain_list(source: &ConfigSource, key: &str) -> Vec<String> {
+    source
+        .list(key)
+        .iter()
+        .map(|domain| idna::domain_to_ascii(domain).unwrap_or_else(|_| domain.to_lowercase()))
+        .collect()
+}
+
+impl EmailConfig {
+    pub fn init(source: &ConfigSource) -> EmailConfig {
+        let mut disposable_domains: Vec<String> = DISPOSABLE_DOMAINS.iter()
+            .map(|domain| domain.to_string())
+            .collect();
+        if let Some(path) = source.get("EMAIL_DISPOSABLE_DOMAINS_FILE") {
+            match std::fs::read_to_string(&path) {
+                Ok(contents) =>
+                    disposable_domains.extend(
+                        contents
+                            .lines()
+                            .map(|line| line.trim())
+                            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                            .map(|line| line.to_lowercase())
+                    ),
+                Err(err) =>
+                    source.error(format!("EMAIL_DISPOSABLE_DOMAINS_FILE can't be read: {}", err)),
+            }
+        }
+
+        EmailConfig {
+            lowercase_local_part: source.parse_or("EMAIL_LOWERCASE_LOCAL_PART", true),
+            allowed_domains: domain_list(source, "EMAIL_ALLOWED_DOMAINS"),
+            denied_domains: domain_list(source, "EMAIL_DENIED_DOMAINS"),
+            reject_disposable: source.parse_or("EMAIL_REJECT_DISPOSABLE", false),
+            disposable_domains,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    // Lengths are counted in user-perceived characters (grapheme clusters), not bytes.
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    // Minimum strength score from 0 (trivially guessable) to 4 (very strong); 0 disables it.
+    pub min_strength: u8,
+    // Reject passwords containing the user's name or parts of their email.
+    pub forbid_context: bool,
+    // Loaded from PASSWORD_BREACHED_LIST_FILE, a file or a directory of range files, when set.
+    pub breached_passwords: Option<Arc<BreachedPasswords>>,
+}
+
+impl PasswordPolicy {
+    pub fn init(source: &ConfigSource) -> PasswordPolicy {
+        let policy = PasswordPolicy {
+            min_length: source.parse_or("PASSWORD_MIN_LENGTH", 6),
+            max_length: source.parse_or("PASSWORD_MAX_LENGTH", 128),
+            require_lowercase: source.parse_or("PASSWORD_REQUIRE_LOWERCASE", false),
+            require_uppercase: source.parse_or("PASSWORD_REQUIRE_UPPERCASE", false),
+            require_digit: source.parse_or("PASSWORD_REQUIRE_DIGIT", true),
+            require_symbol: source.parse_or("PASSWORD_REQUIRE_SYMBOL", true),
+            min_strength: source.parse_or("PASSWORD_MIN_STRENGTH", 0),
+            forbid_context: source.parse_or("PASSWORD_FORBID_CONTEXT", true),
+            breached_passwords: None,
+        };
+        if policy.min_length > policy.max_length {
+            source.error(
+                "PASSWORD_MIN_LENGTH must not be greater than PASSWORD_MAX_LENGTH".to_string()
+            );
+        }
+        if policy.min_strength > 4 {
+            source.error("PASSWORD_MIN_STRENGTH must be between 0 and 4".to_string());
+        }
+
+        let breached_passwords = source.get("PASSWORD_BREACHED_LIST_FILE").and_then(|path| {
+            let false_positive_rate = source.parse_or(
+                "PASSWORD_BREACHED_FALSE_POSITIVE_RATE",
+                0.001
+            );
+            if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+                let message = "PASSWORD_BREACHED_FALSE_POSITIVE_RATE must be between 0 and 1";
+                source.error(message.to_string());
+                return None;
+            }
+            match BreachedPasswords::load(Path::new(&path), false_positive_rate) {
+                Ok(list) => {
+                    log::info!("Loaded breached password list: {:?}", list);
+                    Some(Arc::new(list))
+                }
+                Err(err) => {
+                    source.error(format!("PASSWORD_BREACHED_LIST_FILE can't be loaded: {}", err));
+                    None
+                }
+            }
+        });
+        PasswordPolicy { breached_passwords, ..policy }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HashAlgorithm {
+    Argon2id,
+    Bcrypt,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordHashConfig {
+    // Algorithm for new hashes. Hashes made with the other one still verify and are upgraded on
+    // the next successful login.
+    pub algorithm: HashAlgorithm,
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub bcrypt_cost: u32,
+    // Hashes computed at the same time, each on its own blocking thread.
+    pub max_concurrent_hashes: usize,
+    // How long a request waits for a free hashing slot before getting a 503.
+    pub hash_queue_timeout_ms: u64,
+}
+
+impl PasswordHashConfig {
+    pub fn init(source: &ConfigSource) -> PasswordHashConfig {
+        let algorithm = match
+            source
+                .get("PASSWORD_HASH_ALGORITHM")
+                .unwrap_or_else(|| "argon2id".to_string())
+                .to_lowercase()
+                .as_str()
+        {
+            "argon2id" => HashAlgorithm::Argon2id,
+            "bcrypt" => HashAlgorithm::Bcrypt,
+            other => {
+                source.error(format!("Unknown PASSWORD_HASH_ALGORITHM: {}", other));
+                HashAlgorithm::Argon2id
+            }
+        };
+
+        // Defaults follow the OWASP recommendation for Argon2id.
+        let config = PasswordHashConfig {
+            algorithm,
+            argon2_memory_kib: source.parse_or("ARGON2_MEMORY_KIB", 19456),
+            argon2_iterations: source.parse_or("ARGON2_ITERATIONS", 2),
+            argon2_parallelism: source.parse_or("ARGON2_PARALLELISM", 1),
+            bcrypt_cost: source.parse_or("BCRYPT_COST", 12),
+            max_concurrent_hashes: source.parse_or(
+                "PASSWORD_HASH_MAX_CONCURRENCY",
+                std::thread::available_parallelism().map_or(1, |threads| threads.get())
+            ),
+            hash_queue_timeout_ms: source.parse_or("PASSWORD_HASH_QUEUE_TIMEOUT_MS", 2000),
+        };
+        if config.max_concurrent_hashes == 0 {
+            source.error("PASSWORD_HASH_MAX_CONCURRENCY must be at least 1".to_string());
+        }
+        // Checked here so bad parameters stop startup instead of failing every hash.
+        let argon2_params = argon2::Params::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+            None
+        );
+        if let Err(err) = argon2_params {
+            source.error(format!("Invalid ARGON2_* parameters: {}", err));
+        }
+        if !(4..=31).contains(&config.bcrypt_cost) {
+            source.error("BCRYPT_COST must be between 4 and 31".to_string());
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub bind_address: SocketAddr,
+    // Origins allowed to call the API from a browser.
+    pub cors_origins: Vec<HeaderValue>,
+    // Take the client IP from X-Forwarded-For. Only enable behind a proxy that sets it.
+    pub trust_proxy_headers: bool,
+    // How many proxies in front of the service append to X-Forwarded-For. Entries left of
+    // theirs are sent by the client and can't be trusted.
+    pub trusted_proxy_hops: usize,
+    // Accounts given the admin role when they log in, to bootstrap the first admins.
+    pub admin_emails: Vec<String>,
+    // Hides whether an account exists: login failures share one message and take the same
+    // time, and registering a taken email looks like a success and notifies its owner instead.
+    pub hardened_auth: bool,
+}
+
+impl ServerConfig {
+    pub fn init(source: &ConfigSource) -> ServerConfig {
+        let mut cors_origins = source.list("CORS_ORIGINS");
+        if source.get("CORS_ORIGINS").is_none() {
+            cors_origins.push("http://localhost:3000".to_string());
+        }
+        let cors_origins = cors_origins
+            .iter()
+            .filter_map(|origin| {
+                let value = HeaderValue::from_str(origin);
+                if value.is_err() {
+                    source.error(format!("CORS_ORIGINS has an invalid origin: {}", origin));
+                }
+                value.ok()
+            })
+            .collect();
+        let trusted_proxy_hops = source.parse_or("TRUSTED_PROXY_HOPS", 1);
+        if trusted_proxy_hops == 0 {
+            source.error("TRUSTED_PROXY_HOPS must be at least 1".to_string());
+        }
+
+        ServerConfig {
+            bind_address: source.parse_or("BIND_ADDRESS", SocketAddr::from(([0, 0, 0, 0], 8080))),
+            cors_origins,
+            trust_proxy_headers: source.parse_or("TRUST_PROXY_HEADERS", false),
+            trusted_proxy_hops,
+            admin_emails: source.list("ADMIN_EMAILS"),
+            hardened_auth: source.parse_or("AUTH_HARDENED_MODE", false),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginProtectionConfig {
+    // Failures allowed before every further attempt is delayed.
+    pub account_free_attempts: i64,
+    // Failures after which the account is locked for `lockout_secs`.
+    pub account_lockout_threshold: i64,
+    // Same limits per client IP, set higher since an IP can be shared by many users.
+    pub ip_free_attempts: i64,
+    pub ip_lockout_threshold: i64,
+    // The delay starts at `base_delay_secs` and doubles with each failure up to `max_delay_secs`.
+    pub base_delay_secs: i64,
+    pub max_delay_secs: i64,
+    pub lockout_secs: i64,
+    // Failures older than this are forgotten.
+    pub failure_window_secs: i64,
+    // How often forgotten failures are purged from backends without TTL indexes.
+    pub purge_interval_secs: u64,
+}
+
+impl LoginProtectionConfig {
+    pub fn init(source: &ConfigSource) -> LoginProtectionConfig {
+        let config = LoginProtectionConfig {
+            account_free_attempts: source.parse_or("LOGIN_ACCOUNT_FREE_ATTEMPTS", 3),
+            account_lockout_threshold: source.parse_or("LOGIN_ACCOUNT_LOCKOUT_THRESHOLD", 10),
+            ip_free_attempts: source.parse_or("LOGIN_IP_FREE_ATTEMPTS", 20),
+            ip_lockout_threshold: source.parse_or("LOGIN_IP_LOCKOUT_THRESHOLD", 100),
+            base_delay_secs: source.parse_or("LOGIN_BASE_DELAY_SECS", 1),
+            max_delay_secs: source.parse_or("LOGIN_MAX_DELAY_SECS", 60),
+            lockout_secs: source.parse_or("LOGIN_LOCKOUT_SECS", 900),
+            failure_window_secs: source.parse_or("LOGIN_FAILURE_WINDOW_SECS", 3600),
+            purge_interval_secs: source.parse_or("LOGIN_ATTEMPT_PURGE_INTERVAL_SECS", 3600),
+        };
+        let positive = [
+            ("LOGIN_ACCOUNT_LOCKOUT_THRESHOLD", config.account_lockout_threshold),
+            ("LOGIN_IP_LOCKOUT_THRESHOLD", config.ip_lockout_threshold),
+            ("LOGIN_LOCKOUT_SECS", config.lockout_secs),
+            ("LOGIN_FAILURE_WINDOW_SECS", config.failure_window_secs),
+        ];
+        for (name, value) in positive {
+            if value <= 0 {
+                source.error(format!("{} must be greater than 0", name));
+            }
+        }
+        let non_negative = [
+            ("LOGIN_ACCOUNT_FREE_ATTEMPTS", config.account_free_attempts),
+            ("LOGIN_IP_FREE_ATTEMPTS", config.ip_free_attempts),
+            ("LOGIN_BASE_DELAY_SECS", config.base_delay_secs),
+            ("LOGIN_MAX_DELAY_SECS", config.max_delay_secs),
+        ];
+        for (name, value) in non_negative {
+            if value < 0 {
+                source.error(format!("{} must not be negative", name));
+            }
+        }
+        if config.base_delay_secs > config.max_delay_secs {
+            source.error(
+                "LOGIN_BASE_DELAY_SECS must not be greater than LOGIN_MAX_DELAY_SECS".to_string()
+            );
+        }
+        if config.purge_interval_secs == 0 {
+            source.error("LOGIN_ATTEMPT_PURGE_INTERVAL_SECS must be greater than 0".to_string());
+        }
+        config
+    }
+
+    // How long a counter is kept after its last failure: until it is outside the failure window
+    // and any lock it led to is over.
+    pub fn retention_secs(&self) -> i64 {
+        self.failure_window_secs.max(self.lockout_secs).max(self.max_delay_secs).max(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    // Shares buckets across instances when set. Needs the `redis` feature.
+    pub redis_url: Option<Secret>,
+    // Prepended to every bucket key so several services can share one Redis.
+    pub key_prefix: String,
+}
+
+impl RateLimitConfig {
+    pub fn init(source: &ConfigSource) -> RateLimitConfig {
+        let config = RateLimitConfig {
+            enabled: source.parse_or("RATE_LIMIT_ENABLED", true),
+            redis_url: source.secret("RATE_LIMIT_REDIS_URL"),
+            key_prefix: source.parse_or("RATE_LIMIT_KEY_PREFIX", "rate_limit".to_string()),
+        };
+        if cfg!(not(feature = "redis")) && config.redis_url.is_some() {
+            let message = "RATE_LIMIT_REDIS_URL needs a build with the `redis` feature";
+            source.error(message.to_string());
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    // Signs access and refresh tokens. Changing it logs everyone out.
+    pub jwt_secret: Secret,
+    pub access_token_lifetime_secs: i64,
+    // A session ends when its refresh token goes unused for this long.
+    pub idle_timeout_secs: i64,
+    // A session ends this long after login, however often it is refreshed.
+    pub absolute_lifetime_secs: i64,
+    // How often expired sessions are purged from backends without TTL indexes.
+    pub purge_interval_secs: u64,
+}
+
+impl SessionConfig {
+    pub fn init(source: &ConfigSource) -> SessionConfig {
+        let config = SessionConfig {
+            jwt_secret: source.require_secret("JWT_SECRET"),
+            access_token_lifetime_secs: source.parse_or("ACCESS_TOKEN_LIFETIME_SECS", 300),
+            idle_timeout_secs: source.parse_or("SESSION_IDLE_TIMEOUT_SECS", 86400),
+            absolute_lifetime_secs: source.parse_or("SESSION_ABSOLUTE_LIFETIME_SECS", 2592000),
+            purge_interval_secs: source.parse_or("SESSION_PURGE_INTERVAL_SECS", 3600),
+        };
+        let lifetimes = [
+            ("ACCESS_TOKEN_LIFETIME_SECS", config.access_token_lifetime_secs),
+            ("SESSION_IDLE_TIMEOUT_SECS", config.idle_timeout_secs),
+            ("SESSION_ABSOLUTE_LIFETIME_SECS", config.absolute_lifetime_secs),
+        ];
+        for (key, lifetime) in lifetimes {
+            if lifetime <= 0 {
+                source.error(format!("{} must be greater than 0", key));
+            }
+        }
+        // Short keys can be brute forced from a single token.
+        if !config.jwt_secret.expose().is_empty() && config.jwt_secret.expose().len() < 32 {
+            source.error("JWT_SECRET must be at least 32 bytes long".to_string());
+        }
+        config
+    }
+
+    // Expiry of a session used at `now`: an idle timeout from now, capped by the absolute
+    // lifetime counted from `started_at`.
+    pub fn expires_at(&self, started_at: DateTime, now: DateTime) -> DateTime {
+        let idle_expiry = now.timestamp_millis() + self.idle_timeout_secs * 1000;
+        let absolute_expiry = started_at.timestamp_millis() + self.absolute_lifetime_secs * 1000;
+        DateTime::from_millis(idle_expiry.min(absolute_expiry))
+    }
+}
+
+// Accounts deleted by their owner are kept, blocked, for a grace period in which the deletion
+// can be cancelled, then purged.
+#[derive(Debug, Clone)]
+pub struct AccountDeletionConfig {
+    pub grace_period_secs: i64,
+    // How often accounts past their grace period are purged.
+    pub purge_interval_secs: u64,
+    // Client page that cancels a deletion; the emailed link adds `email` and `code` to it.
+    // Without it, the email only gives the code.
+    pub cancel_url: Option<String>,
+}
+
+impl AccountDeletionConfig {
+    pub fn init(source: &ConfigSource) -> AccountDeletionConfig {
+        let config = AccountDeletionConfig {
+            grace_period_secs: source.parse_or("ACCOUNT_DELETION_GRACE_PERIOD_SECS", 2592000),
+            purge_interval_secs: source.parse_or("ACCOUNT_DELETION_PURGE_INTERVAL_SECS", 3600),
+            cancel_url: source.get("ACCOUNT_DELETION_CANCEL_URL"),
+        };
+        if config.grace_period_secs < 0 {
+            source.error("ACCOUNT_DELETION_GRACE_PERIOD_SECS must not be negative".to_string());
+        }
+        if let Some(url) = &config.cancel_url {
+            if let Err(err) = reqwest::Url::parse(url) {
+                source.error(format!("ACCOUNT_DELETION_CANCEL_URL is not a valid URL: {}", err));
+            }
+        }
+        config
+    }
+
+    // When an account whose deletion was requested at `requested_at` gets purged.
+    pub fn purge_at(&self, requested_at: DateTime) -> DateTime {
+        DateTime::from_millis(requested_at.timestamp_millis() + self.grace_period_secs * 1000)
+    }
+}
+
+// Emailed codes. Deletion cancel codes last as long as the grace period instead.
+#[derive(Debug, Clone)]
+pub struct VerificationCodeConfig {
+    pub verification_lifetime_secs: i64,
+    pub password_reset_lifetime_secs: i64,
+    // How often expired codes are purged from backends without TTL indexes.
+    pub purge_interval_secs: u64,
+}
+
+impl VerificationCodeConfig {
+    pub fn init(source: &ConfigSource) -> VerificationCodeConfig {
+        let config = VerificationCodeConfig {
+            verification_lifetime_secs: source.parse_or("VERIFICATION_CODE_LIFETIME_SECS", 86400),
+            password_reset_lifetime_secs: source.parse_or(
+                "PASSWORD_RESET_CODE_LIFETIME_SECS",
+                3600
+            ),
+            purge_interval_secs: source.parse_or("VERIFICATION_CODE_PURGE_INTERVAL_SECS", 3600),
+        };
+        let lifetimes = [
+            ("VERIFICATION_CODE_LIFETIME_SECS", config.verification_lifetime_secs),
+            ("PASSWORD_RESET_CODE_LIFETIME_SECS", config.password_reset_lifetime_secs),
+        ];
+        for (key, lifetime) in lifetimes {
+            if lifetime <= 0 {
+                source.error(format!("{} must be greater than 0", key));
+            }
         }
+        config
     }
 }
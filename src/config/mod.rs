@@ -1 +1,5 @@
+// Imported as `config::config::...` throughout, so it keeps the name of its parent.
+#[allow(clippy::module_inception)]
 pub mod config;
+pub mod secret;
+pub mod source;
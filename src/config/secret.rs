@@ -0,0 +1,139 @@
+use std::{ collections::HashMap, fmt, path::Path };
+
+use aes_gcm::{ aead::{ Aead, KeyInit, OsRng, AeadCore }, Aes256Gcm, Key, Nonce };
+use base64::{ engine::general_purpose::STANDARD, Engine };
+
+use super::source::flatten_toml;
+
+const NONCE_LEN: usize = 12;
+
+// A setting that must never be logged. `Debug` prints a placeholder, so configs holding one can
+// still derive it; the value is only reachable through `expose`.
+#[derive(Clone, Default, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Secret {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+// Somewhere secrets can be looked up besides plain settings, e.g. a vault or a cloud secret
+// manager. Sources are asked in order and the first one knowing the key wins.
+pub trait SecretSource {
+    // Shown in errors about the secrets it holds.
+    fn name(&self) -> &str;
+
+    fn get(&self, key: &str) -> Result<Option<Secret>, String>;
+}
+
+// Docker and Kubernetes style secrets: `KEY_FILE` names a file holding the value of KEY.
+pub struct SecretFiles {
+    paths: HashMap<String, String>,
+}
+
+impl SecretFiles {
+    pub fn new(values: &HashMap<String, String>) -> SecretFiles {
+        let paths = values
+            .iter()
+            .filter_map(|(key, path)| {
+                key.strip_suffix("_FILE").map(|key| (key.to_string(), path.clone()))
+            })
+            .collect();
+        SecretFiles { paths }
+    }
+}
+
+impl SecretSource for SecretFiles {
+    fn name(&self) -> &str {
+        "KEY_FILE"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Secret>, String> {
+        let Some(path) = self.paths.get(key) else {
+            return Ok(None);
+        };
+        let contents = std::fs
+            ::read_to_string(path)
+            .map_err(|err| format!("{} can't be read: {}", path, err))?;
+        // Editors and `echo` leave a trailing newline that isn't part of the secret.
+        Ok(Some(Secret::new(contents.trim_end_matches(['\r', '\n']).to_string())))
+    }
+}
+
+// A TOML file of secrets encrypted with AES-256-GCM, so it can sit on disk or in a repository.
+// Made with `--encrypt-secrets`; decrypted at startup with SECRETS_KEY.
+pub struct EncryptedSecrets {
+    values: HashMap<String, String>,
+}
+
+impl EncryptedSecrets {
+    pub fn load(path: &Path, key: &Secret) -> Result<EncryptedSecrets, String> {
+        let contents = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let plaintext = decrypt(contents.trim(), key)?;
+        let table: toml::Table = plaintext.parse().map_err(|err: toml::de::Error| {
+            err.message().to_string()
+        })?;
+        let mut values = HashMap::new();
+        flatten_toml("", &table, &mut values);
+        Ok(EncryptedSecrets { values })
+    }
+}
+
+impl SecretSource for EncryptedSecrets {
+    fn name(&self) -> &str {
+        "SECRETS_FILE"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Secret>, String> {
+        Ok(self.values.get(key).cloned().map(Secret::new))
+    }
+}
+
+// A random key for SECRETS_KEY, base64 encoded.
+pub fn generate_key() -> String {
+    STANDARD.encode(Aes256Gcm::generate_key(OsRng))
+}
+
+// The nonce is stored in front of the ciphertext and the whole is base64 encoded.
+pub fn encrypt(plaintext: &str, key: &Secret) -> Result<String, String> {
+    let cipher = cipher(key)?;
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| "encryption failed".to_string())?;
+    Ok(STANDARD.encode([nonce.as_slice(), &ciphertext].concat()))
+}
+
+fn decrypt(encoded: &str, key: &Secret) -> Result<String, String> {
+    let cipher = cipher(key)?;
+    let bytes = STANDARD.decode(encoded).map_err(|_| "not valid base64".to_string())?;
+    if bytes.len() < NONCE_LEN {
+        return Err("too short".to_string());
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        // Also the error for a wrong key, which can't be told apart from tampering.
+        .map_err(|_| "can't be decrypted with SECRETS_KEY".to_string())?;
+    String::from_utf8(plaintext).map_err(|_| "not valid UTF-8".to_string())
+}
+
+fn cipher(key: &Secret) -> Result<Aes256Gcm, String> {
+    let key = STANDARD
+        .decode(key.expose().trim())
+        .ok()
+        .filter(|key| key.len() == 32)
+        .ok_or("SECRETS_KEY must be 32 bytes, base64 encoded".to_string())?;
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
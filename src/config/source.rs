@@ -0,0 +1,288 @@
+use std::{ cell::RefCell, collections::HashMap, fmt, path::{ Path, PathBuf }, str::FromStr };
+
+use clap::Parser;
+
+use super::secret::{ self, EncryptedSecrets, Secret, SecretFiles, SecretSource };
+
+// Read when present and no other file is given.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// Command line flags. Each one overrides the setting of the same name from the other layers.
+#[derive(Debug, Parser)]
+#[command(about = "Auth micro-service")]
+pub struct Cli {
+    #[arg(long, help = "TOML settings file, defaults to CONFIG_FILE or ./config.toml")]
+    config: Option<PathBuf>,
+    #[arg(long, help = "Address to listen on, e.g. 0.0.0.0:8080")]
+    bind_address: Option<String>,
+    #[arg(long = "cors-origin", help = "Origin allowed by CORS, may be repeated")]
+    cors_origins: Vec<String>,
+    #[arg(long, help = "Name of the MongoDB database")]
+    database_name: Option<String>,
+    #[arg(long, help = "Lifetime of access tokens in seconds")]
+    access_token_lifetime_secs: Option<String>,
+    #[arg(long, help = "Idle timeout of sessions and refresh tokens in seconds")]
+    session_idle_timeout_secs: Option<String>,
+    #[arg(long, help = "Absolute lifetime of sessions in seconds")]
+    session_absolute_lifetime_secs: Option<String>,
+    #[arg(long, value_name = "KEY=VALUE", help = "Any other setting, by its env var name")]
+    set: Vec<String>,
+    #[arg(long, help = "Print a new SECRETS_KEY and exit")]
+    generate_secrets_key: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Encrypt a TOML file of secrets with SECRETS_KEY and exit"
+    )]
+    encrypt_secrets: Option<PathBuf>,
+}
+
+impl Cli {
+    fn values(self) -> Result<Vec<(String, String)>, ConfigError> {
+        let mut values = vec![];
+        let flags = [
+            ("BIND_ADDRESS", self.bind_address),
+            ("DATABASE_NAME", self.database_name),
+            ("ACCESS_TOKEN_LIFETIME_SECS", self.access_token_lifetime_secs),
+            ("SESSION_IDLE_TIMEOUT_SECS", self.session_idle_timeout_secs),
+            ("SESSION_ABSOLUTE_LIFETIME_SECS", self.session_absolute_lifetime_secs),
+        ];
+        for (key, value) in flags {
+            if let Some(value) = value {
+                values.push((key.to_string(), value));
+            }
+        }
+        if !self.cors_origins.is_empty() {
+            values.push(("CORS_ORIGINS".to_string(), self.cors_origins.join(",")));
+        }
+        for setting in self.set {
+            match setting.split_once('=') {
+                Some((key, value)) => values.push((key.trim().to_uppercase(), value.to_string())),
+                None => {
+                    let error = format!("--set expects KEY=VALUE, got {}", setting);
+                    return Err(ConfigError(vec![error]));
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+// Every problem found while loading the configuration, so they can be fixed in one go.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for error in &self.0 {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Settings merged from layers, each overriding the ones before it: the TOML file, environment
+// variables and command line flags. Keys are environment variable names; nested TOML tables
+// are joined with underscores, so `[database] name = ".."` sets DATABASE_NAME.
+// Invalid values are recorded instead of panicking and reported together by `finish`.
+pub struct ConfigSource {
+    values: HashMap<String, String>,
+    secret_sources: Vec<Box<dyn SecretSource>>,
+    errors: RefCell<Vec<String>>,
+}
+
+impl ConfigSource {
+    // `extra_secret_sources` are asked after the built-in ones.
+    pub fn load(
+        extra_secret_sources: Vec<Box<dyn SecretSource>>
+    ) -> Result<ConfigSource, ConfigError> {
+        let cli = Cli::parse();
+        if cli.generate_secrets_key {
+            println!("{}", secret::generate_key());
+            std::process::exit(0);
+        }
+        let encrypt_secrets = cli.encrypt_secrets.clone();
+        let mut values = HashMap::new();
+
+        let file = cli.config
+            .clone()
+            .or_else(|| std::env::var_os("CONFIG_FILE").map(PathBuf::from))
+            .or_else(|| {
+                let default = Path::new(DEFAULT_CONFIG_FILE);
+                default.exists().then(|| default.to_path_buf())
+            });
+        if let Some(file) = file {
+            values.extend(read_toml(&file)?);
+        }
+        values.extend(std::env::vars());
+        values.extend(cli.values()?);
+
+        let files = SecretFiles::new(&values);
+        let mut source = ConfigSource {
+            values,
+            secret_sources: vec![Box::new(files)],
+            errors: RefCell::new(vec![]),
+        };
+        if let Some(path) = encrypt_secrets {
+            let key = source
+                .secret("SECRETS_KEY")
+                .ok_or_else(|| ConfigError(vec!["SECRETS_KEY must be set".to_string()]))?;
+            encrypt_file(&path, &key)?;
+            std::process::exit(0);
+        }
+        if let Some(path) = source.get("SECRETS_FILE") {
+            let key = source.require_secret("SECRETS_KEY");
+            match EncryptedSecrets::load(Path::new(&path), &key) {
+                Ok(secrets) => source.secret_sources.push(Box::new(secrets)),
+                Err(err) => source.error(format!("SECRETS_FILE can't be loaded: {}", err)),
+            }
+        }
+        source.secret_sources.extend(extra_secret_sources);
+        Ok(source)
+    }
+
+    // Only the given settings, without files, environment or command line.
+    #[cfg(test)]
+    pub fn from_values(values: &[(&str, &str)]) -> ConfigSource {
+        ConfigSource {
+            values: values
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            secret_sources: vec![],
+            errors: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+
+    pub fn parse_or<T: FromStr>(&self, key: &str, default: T) -> T {
+        match self.values.get(key) {
+            Some(value) =>
+                match value.trim().parse() {
+                    Ok(value) => value,
+                    Err(_) => {
+                        self.error(format!("{} has an invalid value: {}", key, value));
+                        default
+                    }
+                }
+            None => default,
+        }
+    }
+
+    // Records an error when the setting is missing. The empty string returned then is never
+    // used, since `finish` fails.
+    pub fn require(&self, key: &str) -> String {
+        self.get(key).unwrap_or_else(|| {
+            self.error(format!("{} must be set", key));
+            String::new()
+        })
+    }
+
+    // A setting that may also come from `KEY_FILE` or another secret source. A plain setting
+    // wins, then the sources in order.
+    pub fn secret(&self, key: &str) -> Option<Secret> {
+        if let Some(value) = self.get(key) {
+            return Some(Secret::new(value));
+        }
+        for source in &self.secret_sources {
+            match source.get(key) {
+                Ok(Some(secret)) => {
+                    return Some(secret);
+                }
+                Ok(None) => {}
+                Err(err) => self.error(format!("{} from {}: {}", key, source.name(), err)),
+            }
+        }
+        None
+    }
+
+    pub fn require_secret(&self, key: &str) -> Secret {
+        self.secret(key).unwrap_or_else(|| {
+            self.error(format!("{} must be set", key));
+            Secret::default()
+        })
+    }
+
+    // Whether an optional section is configured at all, directly or through `KEY_FILE`.
+    pub fn any_set(&self, keys: &[&str]) -> bool {
+        keys.iter().any(|key| {
+            self.values.contains_key(*key) || self.values.contains_key(&format!("{}_FILE", key))
+        })
+    }
+
+    // Comma separated values, trimmed, without empty entries.
+    pub fn list(&self, key: &str) -> Vec<String> {
+        self.get(key)
+            .unwrap_or_default()
+            .split(',')
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    pub fn error(&self, message: String) {
+        self.errors.borrow_mut().push(message);
+    }
+
+    pub fn finish(self) -> Result<(), ConfigError> {
+        let errors = self.errors.into_inner();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(errors))
+        }
+    }
+}
+
+// Prints the encrypted form of a plain TOML file of secrets, for use as SECRETS_FILE.
+fn encrypt_file(path: &Path, key: &Secret) -> Result<(), ConfigError> {
+    let file_error = |err: String| ConfigError(vec![format!("{}: {}", path.display(), err)]);
+    let plaintext = std::fs::read_to_string(path).map_err(|err| file_error(err.to_string()))?;
+    plaintext.parse::<toml::Table>().map_err(|err| file_error(err.message().to_string()))?;
+    println!("{}", secret::encrypt(&plaintext, key).map_err(file_error)?);
+    Ok(())
+}
+
+fn read_toml(path: &Path) -> Result<HashMap<String, String>, ConfigError> {
+    let file_error = |err: String| ConfigError(vec![format!("{}: {}", path.display(), err)]);
+    let contents = std::fs::read_to_string(path).map_err(|err| file_error(err.to_string()))?;
+    let table: toml::Table = contents.parse().map_err(|err: toml::de::Error| {
+        file_error(err.message().to_string())
+    })?;
+    let mut values = HashMap::new();
+    flatten_toml("", &table, &mut values);
+    Ok(values)
+}
+
+pub(super) fn flatten_toml(
+    prefix: &str,
+    table: &toml::Table,
+    values: &mut HashMap<String, String>
+) {
+    for (key, value) in table {
+        let key = key.replace('-', "_").to_uppercase();
+        let key = if prefix.is_empty() { key } else { format!("{}_{}", prefix, key) };
+        match value {
+            toml::Value::Table(table) => flatten_toml(&key, table, values),
+            toml::Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(toml_scalar).collect();
+                values.insert(key, items.join(","));
+            }
+            value => {
+                values.insert(key, toml_scalar(value));
+            }
+        }
+    }
+}
+
+fn toml_scalar(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,84 @@
+use std::{ fmt, sync::PoisonError };
+
+use axum::http::StatusCode;
+use mongodb::error::{ ErrorKind, WriteFailure };
+
+// Mongo's error code for a unique index violation.
+const MONGO_DUPLICATE_KEY: i32 = 11000;
+
+#[derive(Debug)]
+pub enum StoreError {
+    // The backend could not be reached: connection refused, timeouts, pool exhausted.
+    Unavailable(String),
+    // The backend answered but the operation failed.
+    Query(String),
+    // A stored record could not be turned back into a model.
+    InvalidData(String),
+    // A unique index rejected the write.
+    Duplicate(String),
+}
+
+impl StoreError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            StoreError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            StoreError::Query(_) | StoreError::InvalidData(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            StoreError::Duplicate(_) => StatusCode::CONFLICT,
+        }
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Unavailable(err) => write!(f, "database unavailable: {}", err),
+            StoreError::Query(err) => write!(f, "database query failed: {}", err),
+            StoreError::InvalidData(err) => write!(f, "invalid stored data: {}", err),
+            StoreError::Duplicate(err) => write!(f, "duplicate key: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<mongodb::error::Error> for StoreError {
+    fn from(err: mongodb::error::Error) -> Self {
+        match *err.kind {
+            | ErrorKind::ServerSelection { .. }
+            | ErrorKind::Io(_)
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::DnsResolve { .. } => StoreError::Unavailable(err.to_string()),
+            ErrorKind::Write(WriteFailure::WriteError(ref write_error)) if
+                write_error.code == MONGO_DUPLICATE_KEY
+            => StoreError::Duplicate(err.to_string()),
+            ErrorKind::Command(ref command_error) if command_error.code == MONGO_DUPLICATE_KEY => {
+                StoreError::Duplicate(err.to_string())
+            }
+            _ => StoreError::Query(err.to_string()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for StoreError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::Database(ref db_err) if
+                db_err.kind() == sqlx::error::ErrorKind::UniqueViolation
+            => StoreError::Duplicate(err.to_string()),
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_) => StoreError::Unavailable(err.to_string()),
+            | sqlx::Error::ColumnDecode { .. }
+            | sqlx::Error::ColumnNotFound(_)
+            | sqlx::Error::Decode(_) => StoreError::InvalidData(err.to_string()),
+            _ => StoreError::Query(err.to_string()),
+        }
+    }
+}
+
+impl<T> From<PoisonError<T>> for StoreError {
+    fn from(err: PoisonError<T>) -> Self {
+        StoreError::Query(err.to_string())
+    }
+}
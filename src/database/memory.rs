@@ -0,0 +1,503 @@
+use std::{ cmp::Reverse, collections::HashMap, sync::RwLock };
+
+use async_trait::async_trait;
+use mongodb::bson::{ oid::ObjectId, DateTime };
+
+use crate::{
+    database::{
+        error::StoreError,
+        store::{
+            first_object_id_at,
+            Store,
+            UserStore,
+            TokenStore,
+            LoginAttemptStore,
+            AuditStore,
+            UserQuery,
+            AuditQuery,
+        },
+    },
+    models::{
+        user_model::{ AccountStatus, CodePurpose, User, UserVerificationCode, Email, Password },
+        refresh_token_model::RefreshToken,
+        login_attempt_model::LoginAttempt,
+        audit_event_model::AuditEvent,
+    },
+};
+
+// Keeps everything in process memory. Meant for tests and local runs without a database.
+#[derive(Default)]
+pub struct MemoryStore {
+    users: RwLock<Vec<User>>,
+    verification_codes: RwLock<Vec<UserVerificationCode>>,
+    refresh_tokens: RwLock<Vec<RefreshToken>>,
+    login_attempts: RwLock<HashMap<String, LoginAttempt>>,
+    audit_events: RwLock<Vec<AuditEvent>>,
+}
+
+impl MemoryStore {
+    pub fn init() -> Self {
+        MemoryStore::default()
+    }
+}
+
+fn matches_query(user: &User, query: &UserQuery) -> bool {
+    let search = query.search.as_ref().is_none_or(|search| {
+        let search = search.to_lowercase();
+        user.name.to_lowercase().contains(&search) ||
+            user.email.as_str().to_lowercase().contains(&search)
+    });
+    let login_type = query.login_type
+        .as_ref()
+        .is_none_or(|login_type| login_type.as_str() == user.login_type.as_str());
+    let is_verified = query.is_verified.is_none_or(|is_verified| {
+        user.is_verified.unwrap_or_default() == is_verified
+    });
+    // Same id ranges as the other backends, so dates round down to the second the same way.
+    let after = query.created_after.is_none_or(|after| {
+        user.id.is_some_and(|id| id >= first_object_id_at(after))
+    });
+    let before = query.created_before.is_none_or(|before| {
+        user.id.is_some_and(|id| id < first_object_id_at(before))
+    });
+    search && login_type && is_verified && after && before
+}
+
+fn matches_audit_query(event: &AuditEvent, query: &AuditQuery) -> bool {
+    let user_id = query.user_id.is_none_or(|user_id| event.user_id == Some(user_id));
+    let actor_id = query.actor_id.is_none_or(|actor_id| event.actor_id == Some(actor_id));
+    let email = query.email.as_ref().is_none_or(|email| event.email.as_ref() == Some(email));
+    let event_type = query.event_type.is_none_or(|event_type| event.event_type == event_type);
+    let outcome = query.outcome.is_none_or(|outcome| event.outcome == outcome);
+    let ip = query.ip.as_ref().is_none_or(|ip| event.ip.as_ref() == Some(ip));
+    let after = query.created_after.is_none_or(|after| event.created_at >= after);
+    let before = query.created_before.is_none_or(|before| event.created_at < before);
+    user_id && actor_id && email && event_type && outcome && ip && after && before
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn create_user(&self, new_user: &User) -> Result<ObjectId, StoreError> {
+        let mut users = self.users.write()?;
+        if users.iter().any(|user| user.email.as_str() == new_user.email.as_str()) {
+            return Err(StoreError::Duplicate(format!("email {}", new_user.email.as_str())));
+        }
+        let id = ObjectId::new();
+        let mut data = new_user.clone();
+        data.id = Some(id);
+        users.push(data);
+        Ok(id)
+    }
+
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<Option<User>, StoreError> {
+        let users = self.users.read()?;
+        Ok(users.iter().find(|user| user.id == Some(user_id)).cloned())
+    }
+
+    async fn get_user_by_email(&self, email: String) -> Result<Option<User>, StoreError> {
+        let users = self.users.read()?;
+        Ok(users.iter().find(|user| user.email.as_str() == &email).cloned())
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, StoreError> {
+        Ok(self.users.read()?.clone())
+    }
+
+    async fn list_users_with_role(&self, role: &str) -> Result<Vec<User>, StoreError> {
+        let users = self.users.read()?;
+        Ok(
+            users
+                .iter()
+                .filter(|user| user.roles.iter().any(|granted| granted == role))
+                .cloned()
+                .collect()
+        )
+    }
+
+    async fn search_users(
+        &self,
+        query: &UserQuery,
+        skip: u64,
+        limit: u64
+    ) -> Result<(Vec<User>, u64), StoreError> {
+        let mut users: Vec<User> = self.users
+            .read()?
+            .iter()
+            .filter(|user| matches_query(user, query))
+            .cloned()
+            .collect();
+        users.sort_by_key(|user| Reverse(user.id));
+        let total = users.len() as u64;
+        let page = users.into_iter().skip(skip as usize).take(limit as usize).collect();
+        Ok((page, total))
+    }
+
+    async fn get_users_pending_deletion(
+        &self,
+        requested_before: DateTime
+    ) -> Result<Vec<User>, StoreError> {
+        let users = self.users
+            .read()?
+            .iter()
+            .filter(|user| {
+                user.status == AccountStatus::PENDING_DELETION &&
+                    user.status_changed_at.is_some_and(|at| at <= requested_before)
+            })
+            .cloned()
+            .collect();
+        Ok(users)
+    }
+
+    async fn update_user_email(&self, user_id: ObjectId, email: &str) -> Result<(), StoreError> {
+        let mut users = self.users.write()?;
+        if let Some(user) = users.iter_mut().find(|user| user.id == Some(user_id)) {
+            user.email = Email::from_stored(email.to_string());
+        }
+        Ok(())
+    }
+
+    async fn update_user_verification(&self, email: &str) -> Result<(), StoreError> {
+        let mut users = self.users.write()?;
+        if let Some(user) = users.iter_mut().find(|user| user.email.as_str() == email) {
+            user.is_verified = Some(true);
+        }
+        Ok(())
+    }
+
+    async fn update_user_password(
+        &self,
+        user_id: ObjectId,
+        password: &Password
+    ) -> Result<(), StoreError> {
+        let mut users = self.users.write()?;
+        if let Some(user) = users.iter_mut().find(|user| user.id == Some(user_id)) {
+            user.password = Some(password.clone());
+        }
+        Ok(())
+    }
+
+    async fn set_user_access(
+        &self,
+        user_id: ObjectId,
+        roles: &[String],
+        permissions: &[String]
+    ) -> Result<(), StoreError> {
+        let mut users = self.users.write()?;
+        if let Some(user) = users.iter_mut().find(|user| user.id == Some(user_id)) {
+            user.roles = roles.to_vec();
+            user.permissions = permissions.to_vec();
+        }
+        Ok(())
+    }
+
+    async fn update_user_status(
+        &self,
+        user_id: ObjectId,
+        status: AccountStatus,
+        reason: Option<&str>,
+        now: DateTime
+    ) -> Result<(), StoreError> {
+        let mut users = self.users.write()?;
+        if let Some(user) = users.iter_mut().find(|user| user.id == Some(user_id)) {
+            user.previous_status = Some(user.status);
+            user.status = status;
+            user.status_reason = reason.map(String::from);
+            user.status_changed_at = Some(now);
+        }
+        Ok(())
+    }
+
+    async fn set_password_reset_required(
+        &self,
+        user_id: ObjectId,
+        required: bool
+    ) -> Result<(), StoreError> {
+        let mut users = self.users.write()?;
+        if let Some(user) = users.iter_mut().find(|user| user.id == Some(user_id)) {
+            user.password_reset_required = required;
+        }
+        Ok(())
+    }
+
+    async fn delete_user(&self, user_id: ObjectId) -> Result<u64, StoreError> {
+        let mut users = self.users.write()?;
+        let before = users.len();
+        users.retain(|user| user.id != Some(user_id));
+        Ok((before - users.len()) as u64)
+    }
+
+    async fn store_verification_code(&self, data: UserVerificationCode) -> Result<(), StoreError> {
+        self.verification_codes.write()?.push(data);
+        Ok(())
+    }
+
+    async fn get_verification_code(
+        &self,
+        email: &str,
+        code: &str,
+        purpose: CodePurpose,
+        now: DateTime
+    ) -> Result<Option<UserVerificationCode>, StoreError> {
+        let codes = self.verification_codes.read()?;
+        let res = codes
+            .iter()
+            .find(|stored| {
+                stored.email.as_str() == email &&
+                    stored.code == code &&
+                    stored.purpose == purpose &&
+                    stored.expires_at > now
+            })
+            .cloned();
+        Ok(res)
+    }
+
+    async fn delete_verification_codes(
+        &self,
+        email: &str,
+        purpose: Option<CodePurpose>
+    ) -> Result<(), StoreError> {
+        self.verification_codes
+            .write()
+            ?
+            .retain(|code| {
+                code.email.as_str() != email ||
+                    purpose.is_some_and(|purpose| code.purpose != purpose)
+            });
+        Ok(())
+    }
+
+    async fn count_verification_codes(
+        &self,
+        email: &str,
+        now: DateTime
+    ) -> Result<u64, StoreError> {
+        let codes = self.verification_codes.read()?;
+        let count = codes
+            .iter()
+            .filter(|code| code.email.as_str() == email && code.expires_at > now)
+            .count();
+        Ok(count as u64)
+    }
+
+    async fn delete_expired_verification_codes(&self, now: DateTime) -> Result<u64, StoreError> {
+        let mut codes = self.verification_codes.write()?;
+        let before = codes.len();
+        codes.retain(|code| code.expires_at > now);
+        Ok((before - codes.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl TokenStore for MemoryStore {
+    async fn store_refresh_token(&self, data: RefreshToken) -> Result<(), StoreError> {
+        let mut data = data;
+        data.id.get_or_insert_with(ObjectId::new);
+        self.refresh_tokens.write()?.push(data);
+        Ok(())
+    }
+
+    async fn delete_refresh_token(&self, refresh_token: String) -> Result<u64, StoreError> {
+        let mut tokens = self.refresh_tokens.write()?;
+        let before = tokens.len();
+        tokens.retain(|token| token.refresh_token != refresh_token);
+        Ok((before - tokens.len()) as u64)
+    }
+
+    async fn get_session_by_refresh_token(
+        &self,
+        refresh_token: &str
+    ) -> Result<Option<RefreshToken>, StoreError> {
+        let tokens = self.refresh_tokens.read()?;
+        Ok(tokens.iter().find(|token| token.refresh_token == refresh_token).cloned())
+    }
+
+    async fn get_session(&self, session_id: ObjectId) -> Result<Option<RefreshToken>, StoreError> {
+        let tokens = self.refresh_tokens.read()?;
+        Ok(tokens.iter().find(|token| token.id == Some(session_id)).cloned())
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        refresh_token: &str,
+        new_refresh_token: &str,
+        started_at: DateTime,
+        used_at: DateTime,
+        expires_at: DateTime,
+        ip: &str,
+        user_agent: Option<&str>
+    ) -> Result<Option<RefreshToken>, StoreError> {
+        let mut tokens = self.refresh_tokens.write()?;
+        let token = tokens.iter_mut().find(|token| token.refresh_token == refresh_token);
+        Ok(
+            token.map(|token| {
+                token.refresh_token = new_refresh_token.to_string();
+                token.session_started_at.get_or_insert(started_at);
+                token.last_used_at = Some(used_at);
+                token.expires_at = Some(expires_at);
+                token.ip = Some(ip.to_string());
+                token.user_agent = user_agent.map(String::from);
+                token.clone()
+            })
+        )
+    }
+
+    async fn list_sessions(&self, user_id: ObjectId) -> Result<Vec<RefreshToken>, StoreError> {
+        let tokens = self.refresh_tokens.read()?;
+        let mut sessions: Vec<RefreshToken> = tokens
+            .iter()
+            .filter(|token| token.user_id == Some(user_id))
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| Reverse(session.last_used_at));
+        Ok(sessions)
+    }
+
+    async fn delete_session(
+        &self,
+        user_id: ObjectId,
+        session_id: ObjectId
+    ) -> Result<u64, StoreError> {
+        let mut tokens = self.refresh_tokens.write()?;
+        let before = tokens.len();
+        tokens.retain(|token| token.user_id != Some(user_id) || token.id != Some(session_id));
+        Ok((before - tokens.len()) as u64)
+    }
+
+    async fn delete_expired_sessions(&self, now: DateTime) -> Result<u64, StoreError> {
+        let mut tokens = self.refresh_tokens.write()?;
+        let before = tokens.len();
+        tokens.retain(|token| token.expires_at.is_none_or(|expires_at| expires_at > now));
+        Ok((before - tokens.len()) as u64)
+    }
+
+    async fn start_legacy_sessions(
+        &self,
+        started_at: DateTime,
+        expires_at: DateTime
+    ) -> Result<u64, StoreError> {
+        let mut tokens = self.refresh_tokens.write()?;
+        let mut updated = 0;
+        for token in tokens.iter_mut().filter(|token| token.expires_at.is_none()) {
+            token.session_started_at.get_or_insert(started_at);
+            token.expires_at = Some(expires_at);
+            updated += 1;
+        }
+        Ok(updated)
+    }
+
+    async fn delete_user_sessions(
+        &self,
+        user_id: ObjectId,
+        keep: Option<ObjectId>
+    ) -> Result<u64, StoreError> {
+        let mut tokens = self.refresh_tokens.write()?;
+        let before = tokens.len();
+        tokens.retain(|token| {
+            token.user_id != Some(user_id) || (keep.is_some() && token.id == keep)
+        });
+        Ok((before - tokens.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for MemoryStore {
+    async fn get_login_attempt(&self, key: &str) -> Result<Option<LoginAttempt>, StoreError> {
+        Ok(self.login_attempts.read()?.get(key).cloned())
+    }
+
+    async fn increment_login_failures(&self, key: &str, now: DateTime) -> Result<i64, StoreError> {
+        let mut attempts = self.login_attempts.write()?;
+        let attempt = attempts.entry(key.to_string()).or_insert_with(|| LoginAttempt {
+            key: key.to_string(),
+            failures: 0,
+            last_failure_at: now,
+            locked_until: None,
+        });
+        attempt.failures += 1;
+        attempt.last_failure_at = now;
+        Ok(attempt.failures)
+    }
+
+    async fn lock_login(&self, key: &str, until: DateTime) -> Result<(), StoreError> {
+        if let Some(attempt) = self.login_attempts.write()?.get_mut(key) {
+            attempt.locked_until = Some(until);
+        }
+        Ok(())
+    }
+
+    async fn delete_stale_login_attempts(
+        &self,
+        before: DateTime,
+        now: DateTime
+    ) -> Result<u64, StoreError> {
+        let mut attempts = self.login_attempts.write()?;
+        let count = attempts.len();
+        attempts.retain(|_, attempt| {
+            attempt.last_failure_at >= before ||
+                attempt.locked_until.is_some_and(|until| until > now)
+        });
+        Ok((count - attempts.len()) as u64)
+    }
+
+    async fn clear_login_attempts(&self, key: &str) -> Result<(), StoreError> {
+        self.login_attempts.write()?.remove(key);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditStore for MemoryStore {
+    async fn record_audit_event(&self, event: &AuditEvent) -> Result<(), StoreError> {
+        let data = AuditEvent { id: Some(ObjectId::new()), ..event.clone() };
+        self.audit_events.write()?.push(data);
+        Ok(())
+    }
+
+    async fn search_audit_events(
+        &self,
+        query: &AuditQuery,
+        skip: u64,
+        limit: u64
+    ) -> Result<(Vec<AuditEvent>, u64), StoreError> {
+        let mut events: Vec<AuditEvent> = self.audit_events
+            .read()?
+            .iter()
+            .filter(|event| matches_audit_query(event, query))
+            .cloned()
+            .collect();
+        events.sort_by_key(|event| Reverse((event.created_at, event.id)));
+        let total = events.len() as u64;
+        let page = events.into_iter().skip(skip as usize).take(limit as usize).collect();
+        Ok((page, total))
+    }
+
+    async fn pseudonymize_audit_events(
+        &self,
+        user_id: ObjectId,
+        email: &str,
+        pseudonym: &str
+    ) -> Result<u64, StoreError> {
+        let mut events = self.audit_events.write()?;
+        let mut matched = 0;
+        let of_user = |event: &AuditEvent| {
+            event.user_id == Some(user_id) || event.email.as_deref() == Some(email)
+        };
+        for event in events.iter_mut().filter(|event| of_user(event)) {
+            event.email = Some(pseudonym.to_string());
+            if event.actor_id.is_none_or(|actor_id| actor_id == user_id) {
+                event.ip = None;
+                event.user_agent = None;
+            }
+            matched += 1;
+        }
+        Ok(matched)
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    // Uniqueness is checked on insert instead.
+    async fn ensure_indexes(&self) -> Result<(), StoreError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use mongodb::bson::DateTime;
+
+use crate::config::config::{ EmailConfig, SessionConfig };
+use crate::database::{ error::StoreError, store::Store };
+use crate::models::user_model::{ Email, User };
+
+// The unique email index can't be built while users share an exact email, which the register
+// race of older versions could cause. Those users are reported for an operator to merge instead
+// of failing somewhere inside index creation.
+pub async fn check_duplicate_emails(db: &dyn Store) -> Result<(), StoreError> {
+    let mut by_email: HashMap<String, Vec<String>> = HashMap::new();
+    for user in db.list_users().await? {
+        let id = user.id.map(|id| id.to_hex()).unwrap_or_default();
+        by_email.entry(user.email.as_str().clone()).or_default().push(id);
+    }
+    let mut duplicates: Vec<String> = by_email
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(email, ids)| format!("{} ({})", email, ids.join(", ")))
+        .collect();
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    duplicates.sort();
+    Err(
+        StoreError::Duplicate(
+            format!(
+                "users share an email, merge or remove them before starting: {}",
+                duplicates.join("; ")
+            )
+        )
+    )
+}
+
+// Rewrites stored emails into their canonical form (see `Email::normalize`).
+// Users whose emails only differ by case or whitespace can't be merged automatically, so they are
+// left untouched and reported for an operator to resolve.
+pub async fn normalize_emails(db: &dyn Store, config: &EmailConfig) -> Result<(), StoreError> {
+    let mut by_email: HashMap<String, Vec<User>> = HashMap::new();
+    for user in db.list_users().await? {
+        let canonical = Email::normalize(user.email.as_str(), config);
+        by_email.entry(canonical).or_default().push(user);
+    }
+
+    for (canonical, users) in by_email {
+        if users.len() > 1 {
+            let ids: Vec<String> = users
+                .iter()
+                .filter_map(|user| user.id.map(|id| id.to_hex()))
+                .collect();
+            log::warn!(
+                "{} users share the email {} once normalized: {}",
+                users.len(),
+                canonical,
+                ids.join(", ")
+            );
+            continue;
+        }
+
+        let user = &users[0];
+        if user.email.as_str() != &canonical {
+            if let Some(user_id) = user.id {
+                db.update_user_email(user_id, &canonical).await?;
+                log::info!("Normalized email of user {}", user_id.to_hex());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Sessions stored before expiry was tracked never expire on their own, so they are treated as
+// started now and get the expiry of a new session.
+pub async fn start_legacy_sessions(
+    db: &dyn Store,
+    config: &SessionConfig
+) -> Result<(), StoreError> {
+    let now = DateTime::now();
+    let updated = db.start_legacy_sessions(now, config.expires_at(now, now)).await?;
+    if updated > 0 {
+        log::info!("Set an expiry on {} sessions stored without one", updated);
+    }
+    Ok(())
+}
@@ -1 +1,31 @@
+pub mod error;
 pub mod mongo;
+pub mod memory;
+pub mod migrations;
+pub mod sql;
+pub mod store;
+
+use std::time::Duration;
+
+use crate::config::config::{ DatabaseConfig, DatabaseBackend, EmailConfig };
+use self::{ error::StoreError, mongo::Mongo, memory::MemoryStore, sql::SqlStore, store::Store };
+
+// Connects and prepares the stored data before any index is built on it.
+// `login_attempt_retention` is how long Mongo keeps a failed login counter after its last update.
+pub async fn init_store(
+    config: &DatabaseConfig,
+    email_config: &EmailConfig,
+    login_attempt_retention: Duration
+) -> Result<Box<dyn Store>, StoreError> {
+    let store: Box<dyn Store> = match config.backend {
+        DatabaseBackend::Mongo => {
+            Box::new(Mongo::init(config.url.expose(), &config.name, login_attempt_retention).await?)
+        }
+        DatabaseBackend::Memory => Box::new(MemoryStore::init()),
+        DatabaseBackend::Sql => Box::new(SqlStore::init(config.url.expose()).await?),
+    };
+    migrations::check_duplicate_emails(store.as_ref()).await?;
+    migrations::normalize_emails(store.as_ref(), email_config).await?;
+    store.ensure_indexes().await?;
+    Ok(store)
+}
@@ -1,18 +1,48 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
 use mongodb::{
-    bson::{ extjson::de::Error, doc, oid::ObjectId },
-    results::{ InsertOneResult, UpdateResult, DeleteResult },
-    sync::{ Client, Collection },
+    bson::{ doc, oid::ObjectId, DateTime, Document },
+    options::{ FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument },
+    error::ErrorKind,
+    Client,
+    Collection,
+    IndexModel,
 };
-use std::env;
 use crate::{
-    models::{ user_model::{ User, UserVerificationCode }, refresh_token_model::RefreshToken },
+    database::{
+        error::StoreError,
+        store::{
+            first_object_id_at,
+            Store,
+            UserStore,
+            TokenStore,
+            LoginAttemptStore,
+            AuditStore,
+            UserQuery,
+            AuditQuery,
+        },
+    },
+    models::{
+        user_model::{ AccountStatus, CodePurpose, User, UserVerificationCode, Password },
+        refresh_token_model::RefreshToken,
+        login_attempt_model::LoginAttempt,
+        audit_event_model::AuditEvent,
+    },
 };
 use serde::{ Serialize, Deserialize };
 
+const MONGO_INDEX_OPTIONS_CONFLICT: i32 = 85;
+
 pub struct Mongo {
     user_col: Collection<User>,
     verification_codes_col: Collection<UserVerificationCode>,
     refresh_tokens_col: Collection<RefreshToken>,
+    login_attempts_col: Collection<LoginAttempt>,
+    audit_events_col: Collection<AuditEvent>,
+    // Expiry of the TTL index on `login_attempts`.
+    login_attempt_retention: Duration,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,106 +51,569 @@ pub struct InvalidateTokenPayload {
 }
 
 impl Mongo {
-    pub fn init() -> Self {
-        let uri: String = env::var("MONGO_URI").expect("MONGO_URI environment variable not set");
-
-        let client: Client = Client::with_uri_str(uri).unwrap();
-        let db = client.database("rustDB");
+    pub async fn init(
+        uri: &str,
+        database_name: &str,
+        login_attempt_retention: Duration
+    ) -> Result<Self, StoreError> {
+        let client: Client = Client::with_uri_str(uri).await?;
+        let db = client.database(database_name);
         let user_col: Collection<User> = db.collection("users");
         let verification_codes_col: Collection<UserVerificationCode> =
             db.collection("verification_codes");
         let refresh_tokens_col: Collection<RefreshToken> = db.collection("refresh_tokens");
-        Mongo { user_col, refresh_tokens_col, verification_codes_col }
-    }
-
-    pub fn create_user(&self, new_user: &User) -> Result<InsertOneResult, Error> {
-        let data = User {
-            id: None,
-            name: new_user.name.clone(),
-            email: new_user.email.clone(),
-            password: if let Some(password) = new_user.password.clone() {
-                Some(password)
-            } else {
-                None
-            },
-            is_verified: new_user.is_verified.clone(),
-            login_type: new_user.login_type.clone(),
-        };
-        let user = self.user_col.insert_one(data, None).ok().expect("Error Creating User");
-        Ok(user)
+        let login_attempts_col: Collection<LoginAttempt> = db.collection("login_attempts");
+        let audit_events_col: Collection<AuditEvent> = db.collection("audit_events");
+        Ok(Mongo {
+            user_col,
+            refresh_tokens_col,
+            verification_codes_col,
+            login_attempts_col,
+            audit_events_col,
+            login_attempt_retention,
+        })
     }
+}
 
-    pub fn get_user_by_id(&self, user_id: ObjectId) -> Result<Option<User>, Error> {
+fn user_filter(query: &UserQuery) -> Document {
+    let mut filter = doc! {};
+    if let Some(search) = &query.search {
+        let pattern = regex::escape(search);
+        filter.insert(
+            "$or",
+            vec![
+                doc! { "name": { "$regex": &pattern, "$options": "i" } },
+                doc! { "email": { "$regex": &pattern, "$options": "i" } }
+            ]
+        );
+    }
+    if let Some(login_type) = &query.login_type {
+        filter.insert("login_type", login_type.as_str());
+    }
+    match query.is_verified {
+        Some(true) => filter.insert("is_verified", true),
+        // Users from before verification was tracked have no value and count as unverified.
+        Some(false) => filter.insert("is_verified", doc! { "$ne": true }),
+        None => None,
+    };
+    let mut id_range = doc! {};
+    if let Some(after) = query.created_after {
+        id_range.insert("$gte", first_object_id_at(after));
+    }
+    if let Some(before) = query.created_before {
+        id_range.insert("$lt", first_object_id_at(before));
+    }
+    if !id_range.is_empty() {
+        filter.insert("_id", id_range);
+    }
+    filter
+}
+
+fn audit_filter(query: &AuditQuery) -> Document {
+    let mut filter = doc! {};
+    if let Some(user_id) = query.user_id {
+        filter.insert("user_id", user_id);
+    }
+    if let Some(actor_id) = query.actor_id {
+        filter.insert("actor_id", actor_id);
+    }
+    if let Some(email) = &query.email {
+        filter.insert("email", email);
+    }
+    if let Some(event_type) = query.event_type {
+        filter.insert("event_type", event_type.as_str());
+    }
+    if let Some(outcome) = query.outcome {
+        filter.insert("outcome", outcome.as_str());
+    }
+    if let Some(ip) = &query.ip {
+        filter.insert("ip", ip);
+    }
+    let mut range = doc! {};
+    if let Some(after) = query.created_after {
+        range.insert("$gte", after);
+    }
+    if let Some(before) = query.created_before {
+        range.insert("$lt", before);
+    }
+    if !range.is_empty() {
+        filter.insert("created_at", range);
+    }
+    filter
+}
+
+#[async_trait]
+impl UserStore for Mongo {
+    async fn create_user(&self, new_user: &User) -> Result<ObjectId, StoreError> {
+        let data = User { id: None, ..new_user.clone() };
+        let user = self.user_col.insert_one(data, None).await?;
+        user.inserted_id
+            .as_object_id()
+            .ok_or_else(|| StoreError::InvalidData("inserted user id is not an ObjectId".into()))
+    }
+
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<Option<User>, StoreError> {
         let filter = doc! { "_id": user_id };
-        let user = self.user_col.find_one(filter, None).ok().expect("Error Getting User");
+        let user = self.user_col.find_one(filter, None).await?;
         Ok(user)
     }
 
-    pub fn get_user_by_email(&self, email: String) -> Result<Option<User>, Error> {
+    async fn get_user_by_email(&self, email: String) -> Result<Option<User>, StoreError> {
         let filter = doc! { "email": email };
-        let user = self.user_col.find_one(filter, None).ok().expect("Error Getting User");
+        let user = self.user_col.find_one(filter, None).await?;
         Ok(user)
     }
 
-    pub fn store_verification_code(
+    async fn list_users(&self) -> Result<Vec<User>, StoreError> {
+        let users = self.user_col.find(None, None).await?.try_collect().await?;
+        Ok(users)
+    }
+
+    async fn list_users_with_role(&self, role: &str) -> Result<Vec<User>, StoreError> {
+        // Matches arrays containing the role.
+        let filter = doc! { "roles": role };
+        let users = self.user_col.find(filter, None).await?.try_collect().await?;
+        Ok(users)
+    }
+
+    async fn search_users(
         &self,
-        data: UserVerificationCode
-    ) -> Result<InsertOneResult, Error> {
-        let user = self.verification_codes_col
-            .insert_one(data, None)
-            .ok()
-            .expect("Error in Storing Verification Code Data.");
-        Ok(user)
+        query: &UserQuery,
+        skip: u64,
+        limit: u64
+    ) -> Result<(Vec<User>, u64), StoreError> {
+        let filter = user_filter(query);
+        let total = self.user_col.count_documents(filter.clone(), None).await?;
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .skip(skip)
+            .limit(limit as i64)
+            .build();
+        let users = self.user_col.find(filter, options).await?.try_collect().await?;
+        Ok((users, total))
     }
 
-    pub fn delete_verification_codes(&self, email: &str) -> Result<String, Error> {
-        let filter = doc! {
-            "email": email
+    async fn get_users_pending_deletion(
+        &self,
+        requested_before: DateTime
+    ) -> Result<Vec<User>, StoreError> {
+        let filter =
+            doc! {
+            "status": AccountStatus::PENDING_DELETION.as_str(),
+            "status_changed_at": { "$lte": requested_before },
         };
-        let _ = self.verification_codes_col
-            .delete_many(filter, None)
-            .ok()
-            .expect("Error in Storing Verification Code Data.");
-        Ok("Verification code deleted successfully!".to_string())
+        Ok(self.user_col.find(filter, None).await?.try_collect().await?)
     }
 
-    pub fn get_verification_code(
-        &self,
-        data: UserVerificationCode
-    ) -> Result<Option<UserVerificationCode>, String> {
-        let filter = doc! { "email": data.email.as_str(), "code": data.code };
-        let res = self.verification_codes_col.find_one(filter, None).ok().expect("");
-        Ok(res)
+    async fn update_user_email(&self, user_id: ObjectId, email: &str) -> Result<(), StoreError> {
+        let filter = doc! { "_id": user_id };
+        let update = doc! { "$set": { "email": email } };
+        self.user_col.update_one(filter, update, None).await?;
+        Ok(())
     }
 
-    pub fn update_user_verification(&self, email: &str) -> Result<UpdateResult, Error> {
+    async fn update_user_verification(&self, email: &str) -> Result<(), StoreError> {
         let filter = doc! { "email": email };
         let update = doc! { "$set": { "is_verified": true } };
 
-        let res = self.user_col
-            .update_one(filter, update, None)
-            .ok()
-            .expect("Error Updating User.");
+        let _ = self.user_col
+            .update_one(filter, update, None).await?;
 
+        Ok(())
+    }
+
+    async fn update_user_password(
+        &self,
+        user_id: ObjectId,
+        password: &Password
+    ) -> Result<(), StoreError> {
+        let filter = doc! { "_id": user_id };
+        let update = doc! { "$set": { "password": password.as_str() } };
+        self.user_col.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    async fn set_user_access(
+        &self,
+        user_id: ObjectId,
+        roles: &[String],
+        permissions: &[String]
+    ) -> Result<(), StoreError> {
+        let filter = doc! { "_id": user_id };
+        let update = doc! { "$set": { "roles": roles, "permissions": permissions } };
+        self.user_col.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    async fn update_user_status(
+        &self,
+        user_id: ObjectId,
+        status: AccountStatus,
+        reason: Option<&str>,
+        now: DateTime
+    ) -> Result<(), StoreError> {
+        let filter = doc! { "_id": user_id };
+        // A pipeline, so the old status can be copied. Its stages read "$..." strings as field
+        // paths, hence the literal reason.
+        let update =
+            vec![doc! {
+            "$set": {
+                "previous_status": "$status",
+                "status": status.as_str(),
+                "status_reason": { "$literal": reason },
+                "status_changed_at": now,
+            }
+        }];
+        self.user_col.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    async fn set_password_reset_required(
+        &self,
+        user_id: ObjectId,
+        required: bool
+    ) -> Result<(), StoreError> {
+        let filter = doc! { "_id": user_id };
+        let update = doc! { "$set": { "password_reset_required": required } };
+        self.user_col.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    async fn delete_user(&self, user_id: ObjectId) -> Result<u64, StoreError> {
+        let result = self.user_col.delete_one(doc! { "_id": user_id }, None).await?;
+        Ok(result.deleted_count)
+    }
+
+    async fn store_verification_code(&self, data: UserVerificationCode) -> Result<(), StoreError> {
+        let _ = self.verification_codes_col
+            .insert_one(data, None).await?;
+        Ok(())
+    }
+
+    async fn get_verification_code(
+        &self,
+        email: &str,
+        code: &str,
+        purpose: CodePurpose,
+        now: DateTime
+    ) -> Result<Option<UserVerificationCode>, StoreError> {
+        // The TTL index only removes documents about once a minute.
+        let filter =
+            doc! {
+            "email": email,
+            "code": code,
+            "purpose": purpose.as_str(),
+            "expires_at": { "$gt": now },
+        };
+        let res = self.verification_codes_col.find_one(filter, None).await?;
         Ok(res)
     }
 
-    pub fn store_refresh_token(&self, data: RefreshToken) -> Result<InsertOneResult, Error> {
-        let result = self.refresh_tokens_col
-            .insert_one(data, None)
-            .ok()
-            .expect("Error Invalidating Token");
-        Ok(result)
+    async fn delete_verification_codes(
+        &self,
+        email: &str,
+        purpose: Option<CodePurpose>
+    ) -> Result<(), StoreError> {
+        let mut filter = doc! {
+            "email": email
+        };
+        if let Some(purpose) = purpose {
+            filter.insert("purpose", purpose.as_str());
+        }
+        let _ = self.verification_codes_col
+            .delete_many(filter, None).await?;
+        Ok(())
+    }
+
+    async fn count_verification_codes(
+        &self,
+        email: &str,
+        now: DateTime
+    ) -> Result<u64, StoreError> {
+        let filter = doc! { "email": email, "expires_at": { "$gt": now } };
+        Ok(self.verification_codes_col.count_documents(filter, None).await?)
+    }
+
+    async fn delete_expired_verification_codes(&self, now: DateTime) -> Result<u64, StoreError> {
+        let filter = doc! { "expires_at": { "$lte": now } };
+        Ok(self.verification_codes_col.delete_many(filter, None).await?.deleted_count)
+    }
+}
+
+#[async_trait]
+impl TokenStore for Mongo {
+    async fn store_refresh_token(&self, data: RefreshToken) -> Result<(), StoreError> {
+        let _ = self.refresh_tokens_col
+            .insert_one(data, None).await?;
+        Ok(())
     }
 
-    pub fn delete_refresh_token(&self, refresh_token: String) -> Result<DeleteResult, Error> {
+    async fn delete_refresh_token(&self, refresh_token: String) -> Result<u64, StoreError> {
         let filter = doc! {
             "refresh_token": refresh_token
         };
         let res = self.refresh_tokens_col
-            .delete_many(filter, None)
-            .ok()
-            .expect("Error in Deleting Refresh Token");
-        Ok(res)
+            .delete_many(filter, None).await?;
+        Ok(res.deleted_count)
+    }
+
+    async fn get_session_by_refresh_token(
+        &self,
+        refresh_token: &str
+    ) -> Result<Option<RefreshToken>, StoreError> {
+        let filter = doc! { "refresh_token": refresh_token };
+        let token = self.refresh_tokens_col.find_one(filter, None).await?;
+        Ok(token)
+    }
+
+    async fn get_session(&self, session_id: ObjectId) -> Result<Option<RefreshToken>, StoreError> {
+        let filter = doc! { "_id": session_id };
+        Ok(self.refresh_tokens_col.find_one(filter, None).await?)
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        refresh_token: &str,
+        new_refresh_token: &str,
+        started_at: DateTime,
+        used_at: DateTime,
+        expires_at: DateTime,
+        ip: &str,
+        user_agent: Option<&str>
+    ) -> Result<Option<RefreshToken>, StoreError> {
+        // `null` also matches sessions stored without the field.
+        let filter = doc! { "refresh_token": refresh_token, "session_started_at": null };
+        let update = doc! { "$set": { "session_started_at": started_at } };
+        self.refresh_tokens_col.update_one(filter, update, None).await?;
+
+        let filter = doc! { "refresh_token": refresh_token };
+        let update =
+            doc! {
+            "$set": {
+                "refresh_token": new_refresh_token,
+                "last_used_at": used_at,
+                "expires_at": expires_at,
+                "ip": ip,
+                "user_agent": user_agent,
+            }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let token = self.refresh_tokens_col.find_one_and_update(filter, update, options).await?;
+        Ok(token)
+    }
+
+    async fn list_sessions(&self, user_id: ObjectId) -> Result<Vec<RefreshToken>, StoreError> {
+        let filter = doc! { "user_id": user_id };
+        let options = FindOptions::builder().sort(doc! { "last_used_at": -1 }).build();
+        let sessions = self.refresh_tokens_col.find(filter, options).await?.try_collect().await?;
+        Ok(sessions)
+    }
+
+    async fn delete_session(
+        &self,
+        user_id: ObjectId,
+        session_id: ObjectId
+    ) -> Result<u64, StoreError> {
+        let filter = doc! { "_id": session_id, "user_id": user_id };
+        let res = self.refresh_tokens_col.delete_one(filter, None).await?;
+        Ok(res.deleted_count)
+    }
+
+    // The TTL index normally gets there first; this covers the gap until its next pass.
+    async fn delete_expired_sessions(&self, now: DateTime) -> Result<u64, StoreError> {
+        let filter = doc! { "expires_at": { "$lte": now } };
+        let res = self.refresh_tokens_col.delete_many(filter, None).await?;
+        Ok(res.deleted_count)
+    }
+
+    // The TTL index skips documents without `expires_at`, so these were never removed.
+    async fn start_legacy_sessions(
+        &self,
+        started_at: DateTime,
+        expires_at: DateTime
+    ) -> Result<u64, StoreError> {
+        let filter = doc! { "expires_at": null, "session_started_at": null };
+        let update = doc! { "$set": { "session_started_at": started_at } };
+        self.refresh_tokens_col.update_many(filter, update, None).await?;
+
+        let filter = doc! { "expires_at": null };
+        let update = doc! { "$set": { "expires_at": expires_at } };
+        let res = self.refresh_tokens_col.update_many(filter, update, None).await?;
+        Ok(res.modified_count)
+    }
+
+    async fn delete_user_sessions(
+        &self,
+        user_id: ObjectId,
+        keep: Option<ObjectId>
+    ) -> Result<u64, StoreError> {
+        let mut filter = doc! { "user_id": user_id };
+        if let Some(keep) = keep {
+            filter.insert("_id", doc! { "$ne": keep });
+        }
+        let res = self.refresh_tokens_col.delete_many(filter, None).await?;
+        Ok(res.deleted_count)
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for Mongo {
+    async fn get_login_attempt(&self, key: &str) -> Result<Option<LoginAttempt>, StoreError> {
+        let filter = doc! { "key": key };
+        let attempt = self.login_attempts_col.find_one(filter, None).await?;
+        Ok(attempt)
+    }
+
+    async fn increment_login_failures(&self, key: &str, now: DateTime) -> Result<i64, StoreError> {
+        let filter = doc! { "key": key };
+        let update =
+            doc! {
+            "$inc": { "failures": 1_i64 },
+            "$set": { "last_failure_at": now },
+            "$setOnInsert": { "locked_until": null },
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+        let attempt = self.login_attempts_col.find_one_and_update(filter, update, options).await?;
+        Ok(attempt.map_or(1, |attempt| attempt.failures))
+    }
+
+    async fn lock_login(&self, key: &str, until: DateTime) -> Result<(), StoreError> {
+        let filter = doc! { "key": key };
+        let update = doc! { "$set": { "locked_until": until } };
+        self.login_attempts_col.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    async fn clear_login_attempts(&self, key: &str) -> Result<(), StoreError> {
+        let filter = doc! { "key": key };
+        self.login_attempts_col.delete_many(filter, None).await?;
+        Ok(())
+    }
+
+    async fn delete_stale_login_attempts(
+        &self,
+        before: DateTime,
+        now: DateTime
+    ) -> Result<u64, StoreError> {
+        let filter =
+            doc! {
+            "last_failure_at": { "$lt": before },
+            "$or": [{ "locked_until": null }, { "locked_until": { "$lte": now } }],
+        };
+        Ok(self.login_attempts_col.delete_many(filter, None).await?.deleted_count)
+    }
+}
+
+#[async_trait]
+impl AuditStore for Mongo {
+    async fn record_audit_event(&self, event: &AuditEvent) -> Result<(), StoreError> {
+        let data = AuditEvent { id: None, ..event.clone() };
+        self.audit_events_col.insert_one(data, None).await?;
+        Ok(())
+    }
+
+    async fn search_audit_events(
+        &self,
+        query: &AuditQuery,
+        skip: u64,
+        limit: u64
+    ) -> Result<(Vec<AuditEvent>, u64), StoreError> {
+        let filter = audit_filter(query);
+        let total = self.audit_events_col.count_documents(filter.clone(), None).await?;
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1, "_id": -1 })
+            .skip(skip)
+            .limit(limit as i64)
+            .build();
+        let events = self.audit_events_col.find(filter, options).await?.try_collect().await?;
+        Ok((events, total))
+    }
+
+    async fn pseudonymize_audit_events(
+        &self,
+        user_id: ObjectId,
+        email: &str,
+        pseudonym: &str
+    ) -> Result<u64, StoreError> {
+        let of_user = doc! { "$or": [{ "user_id": user_id }, { "email": email }] };
+        // Before the email changes, since it is part of the match.
+        let mut own_actions = of_user.clone();
+        own_actions.insert("actor_id", doc! { "$in": [null, user_id] });
+        let update = doc! { "$set": { "ip": null, "user_agent": null } };
+        self.audit_events_col.update_many(own_actions, update, None).await?;
+
+        let update = doc! { "$set": { "email": pseudonym } };
+        let res = self.audit_events_col.update_many(of_user, update, None).await?;
+        Ok(res.matched_count)
+    }
+}
+
+#[async_trait]
+impl Store for Mongo {
+    async fn ensure_indexes(&self) -> Result<(), StoreError> {
+        let unique = IndexOptions::builder().unique(true).build();
+
+        let email_index = IndexModel::builder()
+            .keys(doc! { "email": 1 })
+            .options(unique.clone())
+            .build();
+        self.user_col.create_index(email_index, None).await?;
+
+        let code_indexes = vec![
+            IndexModel::builder().keys(doc! { "email": 1, "code": 1 }).build(),
+            // Mongo removes each code once its `expires_at` has passed.
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+                .build()
+        ];
+        self.verification_codes_col.create_indexes(code_indexes, None).await?;
+
+        let token_indexes = vec![
+            IndexModel::builder().keys(doc! { "refresh_token": 1 }).options(unique).build(),
+            IndexModel::builder().keys(doc! { "user_id": 1 }).build(),
+            // Mongo removes each session once its `expires_at` has passed.
+            IndexModel::builder()
+                .keys(doc! { "expires_at": 1 })
+                .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+                .build()
+        ];
+        self.refresh_tokens_col.create_indexes(token_indexes, None).await?;
+
+        let attempt_index = IndexModel::builder()
+            .keys(doc! { "key": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.login_attempts_col.create_index(attempt_index, None).await?;
+        // Mongo removes counters once they are past the failure window and any lock.
+        let expiry_index = IndexModel::builder()
+            .keys(doc! { "last_failure_at": 1 })
+            .options(IndexOptions::builder().expire_after(self.login_attempt_retention).build())
+            .build();
+        if let Err(err) = self.login_attempts_col.create_index(expiry_index.clone(), None).await {
+            // The retention changed since the index was created, so it is rebuilt.
+            let conflict = matches!(
+                *err.kind,
+                ErrorKind::Command(ref error) if error.code == MONGO_INDEX_OPTIONS_CONFLICT
+            );
+            if !conflict {
+                return Err(err.into());
+            }
+            self.login_attempts_col.drop_index("last_failure_at_1", None).await?;
+            self.login_attempts_col.create_index(expiry_index, None).await?;
+        }
+
+        let audit_indexes = vec![
+            IndexModel::builder().keys(doc! { "user_id": 1, "created_at": -1 }).build(),
+            IndexModel::builder().keys(doc! { "actor_id": 1, "created_at": -1 }).build(),
+            IndexModel::builder().keys(doc! { "created_at": -1 }).build()
+        ];
+        self.audit_events_col.create_indexes(audit_indexes, None).await?;
+        Ok(())
     }
 }
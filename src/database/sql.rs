@@ -0,0 +1,897 @@
+use async_trait::async_trait;
+use mongodb::bson::{ oid::ObjectId, DateTime };
+use sqlx::{
+    any::{ AnyPoolOptions, AnyRow },
+    Any,
+    AnyPool,
+    Decode,
+    Row,
+    Type,
+    TypeInfo,
+    ValueRef,
+};
+
+use crate::{
+    database::{
+        error::StoreError,
+        store::{
+            first_object_id_at,
+            Store,
+            UserStore,
+            TokenStore,
+            LoginAttemptStore,
+            AuditStore,
+            UserQuery,
+            AuditQuery,
+        },
+    },
+    models::{
+        user_model::{
+            AccountStatus,
+            CodePurpose,
+            User,
+            UserVerificationCode,
+            Email,
+            Password,
+            LoginTypes,
+        },
+        refresh_token_model::RefreshToken,
+        login_attempt_model::LoginAttempt,
+        audit_event_model::{ AuditEvent, AuditEventType, AuditOutcome },
+    },
+};
+
+// Ids are kept as ObjectId hex strings so records stay interchangeable with the Mongo backend.
+// Timestamps are milliseconds since the epoch. Lists of names are joined with commas.
+const SCHEMA: [&str; 5] = [
+    "CREATE TABLE IF NOT EXISTS users (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        email TEXT NOT NULL,
+        password TEXT,
+        is_verified BOOLEAN,
+        login_type TEXT NOT NULL,
+        roles TEXT,
+        permissions TEXT,
+        status TEXT,
+        status_reason TEXT,
+        status_changed_at BIGINT,
+        previous_status TEXT,
+        password_reset_required BOOLEAN
+    )",
+    "CREATE TABLE IF NOT EXISTS verification_codes (
+        email TEXT NOT NULL,
+        code TEXT NOT NULL,
+        purpose TEXT NOT NULL,
+        expires_at BIGINT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS refresh_tokens (
+        id TEXT PRIMARY KEY,
+        user_id TEXT,
+        email TEXT NOT NULL,
+        refresh_token TEXT NOT NULL,
+        user_agent TEXT,
+        ip TEXT,
+        session_started_at BIGINT,
+        last_used_at BIGINT,
+        expires_at BIGINT
+    )",
+    "CREATE TABLE IF NOT EXISTS login_attempts (
+        attempt_key TEXT PRIMARY KEY,
+        failures BIGINT NOT NULL,
+        last_failure_at BIGINT NOT NULL,
+        locked_until BIGINT
+    )",
+    "CREATE TABLE IF NOT EXISTS audit_events (
+        id TEXT PRIMARY KEY,
+        event_type TEXT NOT NULL,
+        outcome TEXT NOT NULL,
+        reason TEXT,
+        user_id TEXT,
+        actor_id TEXT,
+        email TEXT,
+        ip TEXT,
+        user_agent TEXT,
+        created_at BIGINT NOT NULL
+    )",
+];
+
+const INDEXES: [&str; 9] = [
+    "CREATE UNIQUE INDEX IF NOT EXISTS users_email_idx ON users (email)",
+    "CREATE INDEX IF NOT EXISTS verification_codes_idx ON verification_codes (email, code)",
+    "CREATE INDEX IF NOT EXISTS verification_codes_expiry_idx ON verification_codes (expires_at)",
+    "CREATE UNIQUE INDEX IF NOT EXISTS refresh_tokens_token_idx ON refresh_tokens (refresh_token)",
+    "CREATE INDEX IF NOT EXISTS refresh_tokens_user_id_idx ON refresh_tokens (user_id)",
+    "CREATE INDEX IF NOT EXISTS refresh_tokens_expires_at_idx ON refresh_tokens (expires_at)",
+    "CREATE INDEX IF NOT EXISTS audit_events_user_id_idx ON audit_events (user_id, created_at)",
+    "CREATE INDEX IF NOT EXISTS audit_events_actor_id_idx ON audit_events (actor_id, created_at)",
+    "CREATE INDEX IF NOT EXISTS audit_events_created_at_idx ON audit_events (created_at)",
+];
+
+// SQLite or Postgres through sqlx, picked by the scheme of the connection url.
+pub struct SqlStore {
+    pool: AnyPool,
+}
+
+impl SqlStore {
+    pub async fn init(url: &str) -> Result<Self, StoreError> {
+        sqlx::any::install_default_drivers();
+        let pool = AnyPoolOptions::new().connect(url).await?;
+        // The Any driver can't read NULL from columns SQLite declares as BOOLEAN, and reads
+        // SQLite integers as 32 bits. So there, booleans are declared as the integers SQLite
+        // stores them as anyway, and BIGINT as REAL, which holds millisecond timestamps exactly.
+        let sqlite = url.starts_with("sqlite:");
+        for statement in SCHEMA {
+            let statement = if sqlite {
+                statement.replace("BOOLEAN", "INTEGER").replace("BIGINT", "REAL")
+            } else {
+                statement.to_string()
+            };
+            sqlx::query(&statement).execute(&pool).await?;
+        }
+        Ok(SqlStore { pool })
+    }
+}
+
+fn split_list(value: Option<String>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// The Any driver of sqlx 0.7 can't decode NULL into an `Option`, so nullable columns go through
+// here.
+fn optional<'r, T>(row: &'r AnyRow, column: &str) -> Result<Option<T>, StoreError>
+    where T: Decode<'r, Any> + Type<Any>
+{
+    if row.try_get_raw(column)?.type_info().name() == "NULL" {
+        return Ok(None);
+    }
+    Ok(Some(row.try_get(column)?))
+}
+
+// BIGINT columns, which are REAL on SQLite.
+fn int_column(row: &AnyRow, column: &str) -> Result<Option<i64>, StoreError> {
+    match row.try_get_raw(column)?.type_info().name() {
+        "NULL" => Ok(None),
+        "DOUBLE" => Ok(Some(row.try_get::<f64, _>(column)? as i64)),
+        _ => Ok(Some(row.try_get(column)?)),
+    }
+}
+
+fn millis_column(row: &AnyRow, column: &str) -> Result<Option<DateTime>, StoreError> {
+    Ok(int_column(row, column)?.map(DateTime::from_millis))
+}
+
+// Postgres returns booleans, SQLite the integers they are stored as.
+fn bool_column(row: &AnyRow, column: &str) -> Result<Option<bool>, StoreError> {
+    if row.try_get_raw(column)?.type_info().name() == "BOOLEAN" {
+        return optional(row, column);
+    }
+    Ok(optional::<i64>(row, column)?.map(|value| value != 0))
+}
+
+fn status_column(row: &AnyRow, column: &str) -> Result<Option<AccountStatus>, StoreError> {
+    optional::<String>(row, column)?
+        .map(|status| {
+            AccountStatus::from_stored(&status).ok_or_else(|| {
+                StoreError::InvalidData(format!("unknown account status {}", status))
+            })
+        })
+        .transpose()
+}
+
+fn user_from_row(row: &AnyRow) -> Result<User, StoreError> {
+    let id: String = row.try_get("id")?;
+    let login_type: String = row.try_get("login_type")?;
+    Ok(User {
+        id: Some(
+            ObjectId::parse_str(&id).map_err(|err| StoreError::InvalidData(err.to_string()))?
+        ),
+        name: row.try_get("name")?,
+        email: Email::from_stored(row.try_get("email")?),
+        password: optional::<String>(row, "password")?.map(Password::from_stored),
+        is_verified: bool_column(row, "is_verified")?,
+        login_type: LoginTypes::from_stored(&login_type).ok_or_else(||
+            StoreError::InvalidData(format!("unknown login type {}", login_type))
+        )?,
+        roles: split_list(optional(row, "roles")?),
+        permissions: split_list(optional(row, "permissions")?),
+        status: status_column(row, "status")?.unwrap_or_default(),
+        status_reason: optional(row, "status_reason")?,
+        status_changed_at: millis_column(row, "status_changed_at")?,
+        previous_status: status_column(row, "previous_status")?,
+        password_reset_required: bool_column(row, "password_reset_required")?.unwrap_or_default(),
+    })
+}
+
+enum SqlArg {
+    Text(String),
+    Bool(bool),
+    Int(i64),
+}
+
+// WHERE clause and its arguments for `search_users`.
+fn user_filter(query: &UserQuery) -> (String, Vec<SqlArg>) {
+    let mut conditions = vec![];
+    let mut args = vec![];
+    let mut placeholder = |arg: SqlArg| {
+        args.push(arg);
+        format!("${}", args.len())
+    };
+    if let Some(search) = &query.search {
+        let escaped = search
+            .to_lowercase()
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = placeholder(SqlArg::Text(format!("%{}%", escaped)));
+        conditions.push(
+            format!(
+                "(LOWER(name) LIKE {0} ESCAPE '\\' OR LOWER(email) LIKE {0} ESCAPE '\\')",
+                pattern
+            )
+        );
+    }
+    if let Some(login_type) = &query.login_type {
+        let login_type = placeholder(SqlArg::Text(login_type.as_str().to_string()));
+        conditions.push(format!("login_type = {}", login_type));
+    }
+    if let Some(is_verified) = query.is_verified {
+        let is_verified = placeholder(SqlArg::Bool(is_verified));
+        conditions.push(format!("COALESCE(is_verified, FALSE) = {}", is_verified));
+    }
+    // Hex ObjectIds sort like the ObjectIds themselves, so this compares creation times.
+    if let Some(after) = query.created_after {
+        let after = placeholder(SqlArg::Text(first_object_id_at(after).to_hex()));
+        conditions.push(format!("id >= {}", after));
+    }
+    if let Some(before) = query.created_before {
+        let before = placeholder(SqlArg::Text(first_object_id_at(before).to_hex()));
+        conditions.push(format!("id < {}", before));
+    }
+    if conditions.is_empty() {
+        (String::new(), args)
+    } else {
+        (format!("WHERE {}", conditions.join(" AND ")), args)
+    }
+}
+
+// WHERE clause and its arguments for `search_audit_events`.
+fn audit_filter(query: &AuditQuery) -> (String, Vec<SqlArg>) {
+    let mut conditions = vec![];
+    let mut args = vec![];
+    let mut placeholder = |arg: SqlArg| {
+        args.push(arg);
+        format!("${}", args.len())
+    };
+    if let Some(user_id) = query.user_id {
+        conditions.push(format!("user_id = {}", placeholder(SqlArg::Text(user_id.to_hex()))));
+    }
+    if let Some(actor_id) = query.actor_id {
+        let actor_id = placeholder(SqlArg::Text(actor_id.to_hex()));
+        conditions.push(format!("actor_id = {}", actor_id));
+    }
+    if let Some(email) = &query.email {
+        conditions.push(format!("email = {}", placeholder(SqlArg::Text(email.clone()))));
+    }
+    if let Some(event_type) = query.event_type {
+        let event_type = placeholder(SqlArg::Text(event_type.as_str().to_string()));
+        conditions.push(format!("event_type = {}", event_type));
+    }
+    if let Some(outcome) = query.outcome {
+        let outcome = placeholder(SqlArg::Text(outcome.as_str().to_string()));
+        conditions.push(format!("outcome = {}", outcome));
+    }
+    if let Some(ip) = &query.ip {
+        conditions.push(format!("ip = {}", placeholder(SqlArg::Text(ip.clone()))));
+    }
+    if let Some(after) = query.created_after {
+        let after = placeholder(SqlArg::Int(after.timestamp_millis()));
+        conditions.push(format!("created_at >= {}", after));
+    }
+    if let Some(before) = query.created_before {
+        let before = placeholder(SqlArg::Int(before.timestamp_millis()));
+        conditions.push(format!("created_at < {}", before));
+    }
+    if conditions.is_empty() {
+        (String::new(), args)
+    } else {
+        (format!("WHERE {}", conditions.join(" AND ")), args)
+    }
+}
+
+fn bind_args<'q>(
+    mut query: sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>>,
+    args: &[SqlArg]
+) -> sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>> {
+    for arg in args {
+        query = match arg {
+            SqlArg::Text(value) => query.bind(value.clone()),
+            SqlArg::Bool(value) => query.bind(*value),
+            SqlArg::Int(value) => query.bind(*value),
+        };
+    }
+    query
+}
+
+fn refresh_token_from_row(row: &AnyRow) -> Result<RefreshToken, StoreError> {
+    let parse_id = |id: String| {
+        ObjectId::parse_str(&id).map_err(|err| StoreError::InvalidData(err.to_string()))
+    };
+    Ok(RefreshToken {
+        id: Some(parse_id(row.try_get("id")?)?),
+        user_id: optional::<String>(row, "user_id")?.map(parse_id).transpose()?,
+        email: Email::from_stored(row.try_get("email")?),
+        refresh_token: row.try_get("refresh_token")?,
+        user_agent: optional(row, "user_agent")?,
+        ip: optional(row, "ip")?,
+        session_started_at: millis_column(row, "session_started_at")?,
+        last_used_at: millis_column(row, "last_used_at")?,
+        expires_at: millis_column(row, "expires_at")?,
+    })
+}
+
+fn audit_event_from_row(row: &AnyRow) -> Result<AuditEvent, StoreError> {
+    let parse_id = |id: String| {
+        ObjectId::parse_str(&id).map_err(|err| StoreError::InvalidData(err.to_string()))
+    };
+    let event_type: String = row.try_get("event_type")?;
+    let outcome: String = row.try_get("outcome")?;
+    Ok(AuditEvent {
+        id: Some(parse_id(row.try_get("id")?)?),
+        event_type: AuditEventType::from_stored(&event_type).ok_or_else(||
+            StoreError::InvalidData(format!("unknown audit event type {}", event_type))
+        )?,
+        outcome: AuditOutcome::from_stored(&outcome).ok_or_else(||
+            StoreError::InvalidData(format!("unknown audit outcome {}", outcome))
+        )?,
+        reason: optional(row, "reason")?,
+        user_id: optional::<String>(row, "user_id")?.map(parse_id).transpose()?,
+        actor_id: optional::<String>(row, "actor_id")?.map(parse_id).transpose()?,
+        email: optional(row, "email")?,
+        ip: optional(row, "ip")?,
+        user_agent: optional(row, "user_agent")?,
+        created_at: DateTime::from_millis(int_column(row, "created_at")?.unwrap_or_default()),
+    })
+}
+
+#[async_trait]
+impl UserStore for SqlStore {
+    async fn create_user(&self, new_user: &User) -> Result<ObjectId, StoreError> {
+        let id = ObjectId::new();
+        sqlx::query(
+            "INSERT INTO users
+                (id, name, email, password, is_verified, login_type, roles, permissions, status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+        )
+            .bind(id.to_hex())
+            .bind(new_user.name.clone())
+            .bind(new_user.email.as_str().clone())
+            .bind(new_user.password.as_ref().map(|password| password.as_str().clone()))
+            .bind(new_user.is_verified)
+            .bind(new_user.login_type.as_str())
+            .bind(new_user.roles.join(","))
+            .bind(new_user.permissions.join(","))
+            .bind(new_user.status.as_str())
+            .execute(&self.pool).await?;
+        Ok(id)
+    }
+
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<Option<User>, StoreError> {
+        let row = sqlx::query("SELECT * FROM users WHERE id = $1")
+            .bind(user_id.to_hex())
+            .fetch_optional(&self.pool).await?;
+        row.as_ref().map(user_from_row).transpose()
+    }
+
+    async fn get_user_by_email(&self, email: String) -> Result<Option<User>, StoreError> {
+        let row = sqlx::query("SELECT * FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_optional(&self.pool).await?;
+        row.as_ref().map(user_from_row).transpose()
+    }
+
+    async fn search_users(
+        &self,
+        query: &UserQuery,
+        skip: u64,
+        limit: u64
+    ) -> Result<(Vec<User>, u64), StoreError> {
+        let (filter, args) = user_filter(query);
+        let count = format!("SELECT COUNT(*) AS total FROM users {}", filter);
+        let total: i64 = bind_args(sqlx::query(&count), &args)
+            .fetch_one(&self.pool).await?
+            .try_get("total")?;
+        let select = format!(
+            "SELECT * FROM users {} ORDER BY id DESC LIMIT {} OFFSET {}",
+            filter,
+            limit,
+            skip
+        );
+        let rows = bind_args(sqlx::query(&select), &args).fetch_all(&self.pool).await?;
+        let users = rows.iter().map(user_from_row).collect::<Result<_, _>>()?;
+        Ok((users, total as u64))
+    }
+
+    async fn get_users_pending_deletion(
+        &self,
+        requested_before: DateTime
+    ) -> Result<Vec<User>, StoreError> {
+        let rows = sqlx::query("SELECT * FROM users WHERE status = $1 AND status_changed_at <= $2")
+            .bind(AccountStatus::PENDING_DELETION.as_str())
+            .bind(requested_before.timestamp_millis())
+            .fetch_all(&self.pool).await?;
+        rows.iter().map(user_from_row).collect()
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, StoreError> {
+        let rows = sqlx::query("SELECT * FROM users").fetch_all(&self.pool).await?;
+        rows.iter().map(user_from_row).collect()
+    }
+
+    async fn list_users_with_role(&self, role: &str) -> Result<Vec<User>, StoreError> {
+        // Roles are a comma separated column. LIKE narrows it down and can match other names
+        // containing the role, which the exact comparison below drops.
+        let rows = sqlx::query("SELECT * FROM users WHERE roles LIKE $1")
+            .bind(format!("%{}%", role))
+            .fetch_all(&self.pool).await?;
+        let users: Vec<User> = rows.iter().map(user_from_row).collect::<Result<_, _>>()?;
+        Ok(
+            users
+                .into_iter()
+                .filter(|user| user.roles.iter().any(|granted| granted == role))
+                .collect()
+        )
+    }
+
+    async fn update_user_email(&self, user_id: ObjectId, email: &str) -> Result<(), StoreError> {
+        sqlx::query("UPDATE users SET email = $1 WHERE id = $2")
+            .bind(email.to_string())
+            .bind(user_id.to_hex())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn update_user_verification(&self, email: &str) -> Result<(), StoreError> {
+        sqlx::query("UPDATE users SET is_verified = $1 WHERE email = $2")
+            .bind(true)
+            .bind(email.to_string())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn update_user_password(
+        &self,
+        user_id: ObjectId,
+        password: &Password
+    ) -> Result<(), StoreError> {
+        sqlx::query("UPDATE users SET password = $1 WHERE id = $2")
+            .bind(password.as_str().clone())
+            .bind(user_id.to_hex())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn set_user_access(
+        &self,
+        user_id: ObjectId,
+        roles: &[String],
+        permissions: &[String]
+    ) -> Result<(), StoreError> {
+        sqlx::query("UPDATE users SET roles = $1, permissions = $2 WHERE id = $3")
+            .bind(roles.join(","))
+            .bind(permissions.join(","))
+            .bind(user_id.to_hex())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn update_user_status(
+        &self,
+        user_id: ObjectId,
+        status: AccountStatus,
+        reason: Option<&str>,
+        now: DateTime
+    ) -> Result<(), StoreError> {
+        // The right-hand sides read the row as it was before the update.
+        sqlx::query(
+            "UPDATE users
+            SET previous_status = status, status = $1, status_reason = $2, status_changed_at = $3
+            WHERE id = $4"
+        )
+            .bind(status.as_str())
+            .bind(reason.map(String::from))
+            .bind(now.timestamp_millis())
+            .bind(user_id.to_hex())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn set_password_reset_required(
+        &self,
+        user_id: ObjectId,
+        required: bool
+    ) -> Result<(), StoreError> {
+        sqlx::query("UPDATE users SET password_reset_required = $1 WHERE id = $2")
+            .bind(required)
+            .bind(user_id.to_hex())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn delete_user(&self, user_id: ObjectId) -> Result<u64, StoreError> {
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(user_id.to_hex())
+            .execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn store_verification_code(&self, data: UserVerificationCode) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO verification_codes (email, code, purpose, expires_at)
+            VALUES ($1, $2, $3, $4)"
+        )
+            .bind(data.email.as_str().clone())
+            .bind(data.code)
+            .bind(data.purpose.as_str())
+            .bind(data.expires_at.timestamp_millis())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn get_verification_code(
+        &self,
+        email: &str,
+        code: &str,
+        purpose: CodePurpose,
+        now: DateTime
+    ) -> Result<Option<UserVerificationCode>, StoreError> {
+        let row = sqlx::query(
+            "SELECT * FROM verification_codes
+            WHERE email = $1 AND code = $2 AND purpose = $3 AND expires_at > $4"
+        )
+            .bind(email.to_string())
+            .bind(code.to_string())
+            .bind(purpose.as_str())
+            .bind(now.timestamp_millis())
+            .fetch_optional(&self.pool).await?;
+        match row {
+            Some(row) =>
+                Ok(
+                    Some(UserVerificationCode {
+                        email: Email::from_stored(row.try_get("email")?),
+                        code: row.try_get("code")?,
+                        purpose,
+                        expires_at: DateTime::from_millis(
+                            int_column(&row, "expires_at")?.unwrap_or_default()
+                        ),
+                    })
+                ),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_verification_codes(
+        &self,
+        email: &str,
+        purpose: Option<CodePurpose>
+    ) -> Result<(), StoreError> {
+        match purpose {
+            Some(purpose) => {
+                sqlx::query("DELETE FROM verification_codes WHERE email = $1 AND purpose = $2")
+                    .bind(email.to_string())
+                    .bind(purpose.as_str())
+                    .execute(&self.pool).await?
+            }
+            None => {
+                sqlx::query("DELETE FROM verification_codes WHERE email = $1")
+                    .bind(email.to_string())
+                    .execute(&self.pool).await?
+            }
+        };
+        Ok(())
+    }
+
+    async fn count_verification_codes(
+        &self,
+        email: &str,
+        now: DateTime
+    ) -> Result<u64, StoreError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) AS total FROM verification_codes WHERE email = $1 AND expires_at > $2"
+        )
+            .bind(email.to_string())
+            .bind(now.timestamp_millis())
+            .fetch_one(&self.pool).await?;
+        Ok(row.try_get::<i64, _>("total")? as u64)
+    }
+
+    async fn delete_expired_verification_codes(&self, now: DateTime) -> Result<u64, StoreError> {
+        let result = sqlx::query("DELETE FROM verification_codes WHERE expires_at <= $1")
+            .bind(now.timestamp_millis())
+            .execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl TokenStore for SqlStore {
+    async fn store_refresh_token(&self, data: RefreshToken) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO refresh_tokens
+            (id, user_id, email, refresh_token, user_agent, ip, session_started_at, last_used_at,
+            expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"
+        )
+            .bind(data.id.unwrap_or_default().to_hex())
+            .bind(data.user_id.map(|user_id| user_id.to_hex()))
+            .bind(data.email.as_str().clone())
+            .bind(data.refresh_token)
+            .bind(data.user_agent)
+            .bind(data.ip)
+            .bind(data.session_started_at.map(|started_at| started_at.timestamp_millis()))
+            .bind(data.last_used_at.map(|last_used_at| last_used_at.timestamp_millis()))
+            .bind(data.expires_at.map(|expires_at| expires_at.timestamp_millis()))
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn delete_refresh_token(&self, refresh_token: String) -> Result<u64, StoreError> {
+        let res = sqlx::query("DELETE FROM refresh_tokens WHERE refresh_token = $1")
+            .bind(refresh_token)
+            .execute(&self.pool).await?;
+        Ok(res.rows_affected())
+    }
+
+    async fn get_session_by_refresh_token(
+        &self,
+        refresh_token: &str
+    ) -> Result<Option<RefreshToken>, StoreError> {
+        let row = sqlx::query("SELECT * FROM refresh_tokens WHERE refresh_token = $1")
+            .bind(refresh_token.to_string())
+            .fetch_optional(&self.pool).await?;
+        row.as_ref().map(refresh_token_from_row).transpose()
+    }
+
+    async fn get_session(&self, session_id: ObjectId) -> Result<Option<RefreshToken>, StoreError> {
+        let row = sqlx::query("SELECT * FROM refresh_tokens WHERE id = $1")
+            .bind(session_id.to_hex())
+            .fetch_optional(&self.pool).await?;
+        row.as_ref().map(refresh_token_from_row).transpose()
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        refresh_token: &str,
+        new_refresh_token: &str,
+        started_at: DateTime,
+        used_at: DateTime,
+        expires_at: DateTime,
+        ip: &str,
+        user_agent: Option<&str>
+    ) -> Result<Option<RefreshToken>, StoreError> {
+        let row = sqlx::query(
+            "UPDATE refresh_tokens
+            SET refresh_token = $1, last_used_at = $2, expires_at = $3, ip = $4, user_agent = $5,
+            session_started_at = COALESCE(session_started_at, $6)
+            WHERE refresh_token = $7
+            RETURNING *"
+        )
+            .bind(new_refresh_token.to_string())
+            .bind(used_at.timestamp_millis())
+            .bind(expires_at.timestamp_millis())
+            .bind(ip.to_string())
+            .bind(user_agent.map(String::from))
+            .bind(started_at.timestamp_millis())
+            .bind(refresh_token.to_string())
+            .fetch_optional(&self.pool).await?;
+        row.as_ref().map(refresh_token_from_row).transpose()
+    }
+
+    async fn list_sessions(&self, user_id: ObjectId) -> Result<Vec<RefreshToken>, StoreError> {
+        let rows = sqlx::query(
+            "SELECT * FROM refresh_tokens WHERE user_id = $1 ORDER BY last_used_at DESC"
+        )
+            .bind(user_id.to_hex())
+            .fetch_all(&self.pool).await?;
+        rows.iter().map(refresh_token_from_row).collect()
+    }
+
+    async fn delete_session(
+        &self,
+        user_id: ObjectId,
+        session_id: ObjectId
+    ) -> Result<u64, StoreError> {
+        let res = sqlx::query("DELETE FROM refresh_tokens WHERE id = $1 AND user_id = $2")
+            .bind(session_id.to_hex())
+            .bind(user_id.to_hex())
+            .execute(&self.pool).await?;
+        Ok(res.rows_affected())
+    }
+
+    async fn delete_expired_sessions(&self, now: DateTime) -> Result<u64, StoreError> {
+        let res = sqlx::query("DELETE FROM refresh_tokens WHERE expires_at <= $1")
+            .bind(now.timestamp_millis())
+            .execute(&self.pool).await?;
+        Ok(res.rows_affected())
+    }
+
+    async fn start_legacy_sessions(
+        &self,
+        started_at: DateTime,
+        expires_at: DateTime
+    ) -> Result<u64, StoreError> {
+        let res = sqlx::query(
+            "UPDATE refresh_tokens
+            SET session_started_at = COALESCE(session_started_at, $1), expires_at = $2
+            WHERE expires_at IS NULL"
+        )
+            .bind(started_at.timestamp_millis())
+            .bind(expires_at.timestamp_millis())
+            .execute(&self.pool).await?;
+        Ok(res.rows_affected())
+    }
+
+    async fn delete_user_sessions(
+        &self,
+        user_id: ObjectId,
+        keep: Option<ObjectId>
+    ) -> Result<u64, StoreError> {
+        // An empty id matches no session, so nothing is kept.
+        let keep = keep.map(|keep| keep.to_hex()).unwrap_or_default();
+        let res = sqlx::query("DELETE FROM refresh_tokens WHERE user_id = $1 AND id <> $2")
+            .bind(user_id.to_hex())
+            .bind(keep)
+            .execute(&self.pool).await?;
+        Ok(res.rows_affected())
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for SqlStore {
+    async fn get_login_attempt(&self, key: &str) -> Result<Option<LoginAttempt>, StoreError> {
+        let row = sqlx::query("SELECT * FROM login_attempts WHERE attempt_key = $1")
+            .bind(key.to_string())
+            .fetch_optional(&self.pool).await?;
+        match row {
+            Some(row) =>
+                Ok(
+                    Some(LoginAttempt {
+                        key: row.try_get("attempt_key")?,
+                        failures: int_column(&row, "failures")?.unwrap_or_default(),
+                        last_failure_at: DateTime::from_millis(
+                            int_column(&row, "last_failure_at")?.unwrap_or_default()
+                        ),
+                        locked_until: millis_column(&row, "locked_until")?,
+                    })
+                ),
+            None => Ok(None),
+        }
+    }
+
+    async fn increment_login_failures(&self, key: &str, now: DateTime) -> Result<i64, StoreError> {
+        let row = sqlx::query(
+            "INSERT INTO login_attempts (attempt_key, failures, last_failure_at) VALUES ($1, 1, $2)
+            ON CONFLICT (attempt_key) DO UPDATE
+            SET failures = login_attempts.failures + 1, last_failure_at = $2
+            RETURNING failures"
+        )
+            .bind(key.to_string())
+            .bind(now.timestamp_millis())
+            .fetch_one(&self.pool).await?;
+        Ok(int_column(&row, "failures")?.unwrap_or_default())
+    }
+
+    async fn lock_login(&self, key: &str, until: DateTime) -> Result<(), StoreError> {
+        sqlx::query("UPDATE login_attempts SET locked_until = $1 WHERE attempt_key = $2")
+            .bind(until.timestamp_millis())
+            .bind(key.to_string())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn delete_stale_login_attempts(
+        &self,
+        before: DateTime,
+        now: DateTime
+    ) -> Result<u64, StoreError> {
+        let res = sqlx::query(
+            "DELETE FROM login_attempts
+            WHERE last_failure_at < $1 AND (locked_until IS NULL OR locked_until <= $2)"
+        )
+            .bind(before.timestamp_millis())
+            .bind(now.timestamp_millis())
+            .execute(&self.pool).await?;
+        Ok(res.rows_affected())
+    }
+
+    async fn clear_login_attempts(&self, key: &str) -> Result<(), StoreError> {
+        sqlx::query("DELETE FROM login_attempts WHERE attempt_key = $1")
+            .bind(key.to_string())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditStore for SqlStore {
+    async fn record_audit_event(&self, event: &AuditEvent) -> Result<(), StoreError> {
+        sqlx::query(
+            "INSERT INTO audit_events
+                (id, event_type, outcome, reason, user_id, actor_id, email, ip, user_agent,
+                created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"
+        )
+            .bind(ObjectId::new().to_hex())
+            .bind(event.event_type.as_str())
+            .bind(event.outcome.as_str())
+            .bind(event.reason.clone())
+            .bind(event.user_id.map(|user_id| user_id.to_hex()))
+            .bind(event.actor_id.map(|actor_id| actor_id.to_hex()))
+            .bind(event.email.clone())
+            .bind(event.ip.clone())
+            .bind(event.user_agent.clone())
+            .bind(event.created_at.timestamp_millis())
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn search_audit_events(
+        &self,
+        query: &AuditQuery,
+        skip: u64,
+        limit: u64
+    ) -> Result<(Vec<AuditEvent>, u64), StoreError> {
+        let (filter, args) = audit_filter(query);
+        let count = format!("SELECT COUNT(*) AS total FROM audit_events {}", filter);
+        let total: i64 = bind_args(sqlx::query(&count), &args)
+            .fetch_one(&self.pool).await?
+            .try_get("total")?;
+        let select = format!(
+            "SELECT * FROM audit_events {} ORDER BY created_at DESC, id DESC LIMIT {} OFFSET {}",
+            filter,
+            limit,
+            skip
+        );
+        let rows = bind_args(sqlx::query(&select), &args).fetch_all(&self.pool).await?;
+        let events = rows.iter().map(audit_event_from_row).collect::<Result<_, _>>()?;
+        Ok((events, total as u64))
+    }
+
+    async fn pseudonymize_audit_events(
+        &self,
+        user_id: ObjectId,
+        email: &str,
+        pseudonym: &str
+    ) -> Result<u64, StoreError> {
+        // Before the email changes, since it is part of the match.
+        sqlx::query(
+            "UPDATE audit_events SET ip = NULL, user_agent = NULL
+            WHERE (user_id = $1 OR email = $2) AND (actor_id IS NULL OR actor_id = $3)"
+        )
+            .bind(user_id.to_hex())
+            .bind(email.to_string())
+            .bind(user_id.to_hex())
+            .execute(&self.pool).await?;
+        let res = sqlx::query("UPDATE audit_events SET email = $1 WHERE user_id = $2 OR email = $3")
+            .bind(pseudonym.to_string())
+            .bind(user_id.to_hex())
+            .bind(email.to_string())
+            .execute(&self.pool).await?;
+        Ok(res.rows_affected())
+    }
+}
+
+#[async_trait]
+impl Store for SqlStore {
+    async fn ensure_indexes(&self) -> Result<(), StoreError> {
+        for statement in INDEXES {
+            sqlx::query(statement).execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+}
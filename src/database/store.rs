@@ -0,0 +1,240 @@
+use async_trait::async_trait;
+use mongodb::bson::{ oid::ObjectId, DateTime };
+
+use crate::database::error::StoreError;
+use crate::models::{
+    user_model::{ AccountStatus, CodePurpose, LoginTypes, User, UserVerificationCode, Password },
+    refresh_token_model::RefreshToken,
+    login_attempt_model::LoginAttempt,
+    audit_event_model::{ AuditEvent, AuditEventType, AuditOutcome },
+};
+
+// Filters for `search_users`. Unset fields match every user.
+#[derive(Debug, Clone, Default)]
+pub struct UserQuery {
+    // Case-insensitive part of the name or email.
+    pub search: Option<String>,
+    pub login_type: Option<LoginTypes>,
+    pub is_verified: Option<bool>,
+    pub created_after: Option<DateTime>,
+    pub created_before: Option<DateTime>,
+}
+
+// Filters for `search_audit_events`. Unset fields match every event.
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub user_id: Option<ObjectId>,
+    pub actor_id: Option<ObjectId>,
+    pub email: Option<String>,
+    pub event_type: Option<AuditEventType>,
+    pub outcome: Option<AuditOutcome>,
+    pub ip: Option<String>,
+    pub created_after: Option<DateTime>,
+    pub created_before: Option<DateTime>,
+}
+
+// The smallest ObjectId made at `time`. Ids sort by creation time, so ranges of them select
+// users by when they registered, including users stored before anything else recorded it.
+pub fn first_object_id_at(time: DateTime) -> ObjectId {
+    let secs = (time.timestamp_millis().div_euclid(1000)).clamp(0, u32::MAX as i64) as u32;
+    let mut bytes = [0u8; 12];
+    bytes[..4].copy_from_slice(&secs.to_be_bytes());
+    ObjectId::from_bytes(bytes)
+}
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn create_user(&self, new_user: &User) -> Result<ObjectId, StoreError>;
+
+    async fn get_user_by_id(&self, user_id: ObjectId) -> Result<Option<User>, StoreError>;
+
+    async fn get_user_by_email(&self, email: String) -> Result<Option<User>, StoreError>;
+
+    async fn list_users(&self) -> Result<Vec<User>, StoreError>;
+
+    // Users granted `role`, whatever their status.
+    async fn list_users_with_role(&self, role: &str) -> Result<Vec<User>, StoreError>;
+
+    // A page of matching users, newest first, and how many match in total.
+    async fn search_users(
+        &self,
+        query: &UserQuery,
+        skip: u64,
+        limit: u64
+    ) -> Result<(Vec<User>, u64), StoreError>;
+
+    // Users whose own deletion request was made at or before `requested_before`.
+    async fn get_users_pending_deletion(
+        &self,
+        requested_before: DateTime
+    ) -> Result<Vec<User>, StoreError>;
+
+    async fn update_user_email(&self, user_id: ObjectId, email: &str) -> Result<(), StoreError>;
+
+    async fn update_user_verification(&self, email: &str) -> Result<(), StoreError>;
+
+    async fn update_user_password(
+        &self,
+        user_id: ObjectId,
+        password: &Password
+    ) -> Result<(), StoreError>;
+
+    async fn set_user_access(
+        &self,
+        user_id: ObjectId,
+        roles: &[String],
+        permissions: &[String]
+    ) -> Result<(), StoreError>;
+
+    // Also records why and when, replacing the previous reason, and keeps the status it replaces
+    // as `previous_status`.
+    async fn update_user_status(
+        &self,
+        user_id: ObjectId,
+        status: AccountStatus,
+        reason: Option<&str>,
+        now: DateTime
+    ) -> Result<(), StoreError>;
+
+    async fn set_password_reset_required(
+        &self,
+        user_id: ObjectId,
+        required: bool
+    ) -> Result<(), StoreError>;
+
+    // Returns how many users were deleted, 0 or 1.
+    async fn delete_user(&self, user_id: ObjectId) -> Result<u64, StoreError>;
+
+    async fn store_verification_code(&self, data: UserVerificationCode) -> Result<(), StoreError>;
+
+    // The code, if it was sent to `email` for `purpose` and is still valid at `now`.
+    async fn get_verification_code(
+        &self,
+        email: &str,
+        code: &str,
+        purpose: CodePurpose,
+        now: DateTime
+    ) -> Result<Option<UserVerificationCode>, StoreError>;
+
+    // Deletes the codes sent to `email` for `purpose`, or for anything when None.
+    async fn delete_verification_codes(
+        &self,
+        email: &str,
+        purpose: Option<CodePurpose>
+    ) -> Result<(), StoreError>;
+
+    // How many unused, unexpired codes were sent to `email`.
+    async fn count_verification_codes(
+        &self,
+        email: &str,
+        now: DateTime
+    ) -> Result<u64, StoreError>;
+
+    // Returns how many codes were deleted.
+    async fn delete_expired_verification_codes(&self, now: DateTime) -> Result<u64, StoreError>;
+}
+
+#[async_trait]
+pub trait TokenStore: Send + Sync {
+    async fn store_refresh_token(&self, data: RefreshToken) -> Result<(), StoreError>;
+
+    async fn delete_refresh_token(&self, refresh_token: String) -> Result<u64, StoreError>;
+
+    async fn get_session_by_refresh_token(
+        &self,
+        refresh_token: &str
+    ) -> Result<Option<RefreshToken>, StoreError>;
+
+    async fn get_session(&self, session_id: ObjectId) -> Result<Option<RefreshToken>, StoreError>;
+
+    // Replaces the token of a session, extends it and records where it was used from. Returns
+    // the updated session, or None when the old token is unknown because it was rotated or
+    // revoked in the meantime. `started_at` is only stored when the session has no start yet.
+    #[allow(clippy::too_many_arguments)]
+    async fn rotate_refresh_token(
+        &self,
+        refresh_token: &str,
+        new_refresh_token: &str,
+        started_at: DateTime,
+        used_at: DateTime,
+        expires_at: DateTime,
+        ip: &str,
+        user_agent: Option<&str>
+    ) -> Result<Option<RefreshToken>, StoreError>;
+
+    async fn list_sessions(&self, user_id: ObjectId) -> Result<Vec<RefreshToken>, StoreError>;
+
+    async fn delete_session(
+        &self,
+        user_id: ObjectId,
+        session_id: ObjectId
+    ) -> Result<u64, StoreError>;
+
+    async fn delete_expired_sessions(&self, now: DateTime) -> Result<u64, StoreError>;
+
+    // Gives sessions stored before expiry was tracked a start and an expiry, so they end like
+    // any other session. Returns how many were updated.
+    async fn start_legacy_sessions(
+        &self,
+        started_at: DateTime,
+        expires_at: DateTime
+    ) -> Result<u64, StoreError>;
+
+    // Deletes every session of the user except `keep`, if given.
+    async fn delete_user_sessions(
+        &self,
+        user_id: ObjectId,
+        keep: Option<ObjectId>
+    ) -> Result<u64, StoreError>;
+}
+
+#[async_trait]
+pub trait LoginAttemptStore: Send + Sync {
+    async fn get_login_attempt(&self, key: &str) -> Result<Option<LoginAttempt>, StoreError>;
+
+    // Atomically adds one failure, creating the counter if needed. Returns the new count.
+    async fn increment_login_failures(&self, key: &str, now: DateTime) -> Result<i64, StoreError>;
+
+    async fn lock_login(&self, key: &str, until: DateTime) -> Result<(), StoreError>;
+
+    async fn clear_login_attempts(&self, key: &str) -> Result<(), StoreError>;
+
+    // Deletes counters whose last failure was before `before` and that aren't locked at `now`.
+    // Returns how many were deleted.
+    async fn delete_stale_login_attempts(
+        &self,
+        before: DateTime,
+        now: DateTime
+    ) -> Result<u64, StoreError>;
+}
+
+// Append-only: there is no way to change or remove an event once recorded.
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    async fn record_audit_event(&self, event: &AuditEvent) -> Result<(), StoreError>;
+
+    // A page of matching events, newest first, and how many match in total.
+    async fn search_audit_events(
+        &self,
+        query: &AuditQuery,
+        skip: u64,
+        limit: u64
+    ) -> Result<(Vec<AuditEvent>, u64), StoreError>;
+
+    // The only change made to recorded events, for erased accounts: events of the user or made
+    // with their email get `pseudonym` as email, and those no one else acted in lose their IP
+    // and user agent. Returns how many events matched.
+    async fn pseudonymize_audit_events(
+        &self,
+        user_id: ObjectId,
+        email: &str,
+        pseudonym: &str
+    ) -> Result<u64, StoreError>;
+}
+
+// Everything the services need from a storage backend.
+#[async_trait]
+pub trait Store: UserStore + TokenStore + LoginAttemptStore + AuditStore {
+    // Creates the indexes and unique constraints the services rely on. Safe to run on every boot.
+    async fn ensure_indexes(&self) -> Result<(), StoreError>;
+}
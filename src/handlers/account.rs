@@ -0,0 +1,52 @@
+use std::sync::Arc;
+use axum::{ extract::{ Json, Query }, http::StatusCode, response::Response };
+use axum::extract::State;
+use serde_json::Value;
+
+use crate::{
+    services::account::{
+        request_account_deletion_service,
+        cancel_account_deletion_service,
+        export_account_data_service,
+    },
+    services::audit::list_security_events_service,
+    models::error_model::AppError,
+    utils::{
+        auth_user::AuthUser,
+        client_info::ClientInfo,
+        form_data::{ AuditEventListQuery, DeleteAccountForm, VerificationCodeForm },
+    },
+};
+use crate::AppState;
+
+pub async fn delete_account_handler(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    Json(form): Json<DeleteAccountForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    request_account_deletion_service(State(app_state), auth_user, client, Json(form)).await
+}
+
+pub async fn export_account_data_handler(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser
+) -> Result<Response, AppError> {
+    export_account_data_service(State(app_state), auth_user).await
+}
+
+pub async fn security_events_handler(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Query(query): Query<AuditEventListQuery>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    list_security_events_service(State(app_state), auth_user, query).await
+}
+
+pub async fn cancel_account_deletion_handler(
+    State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(form): Json<VerificationCodeForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    cancel_account_deletion_service(State(app_state), client, Json(form)).await
+}
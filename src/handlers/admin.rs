@@ -0,0 +1,132 @@
+use std::sync::Arc;
+use axum::{ extract::{ Json, Path, Query }, http::StatusCode };
+use axum::extract::State;
+use serde_json::Value;
+
+use crate::{
+    services::{
+        admin::{
+            set_user_access_service,
+            list_users_service,
+            get_user_service,
+            set_user_status_service,
+            verify_user_service,
+            force_password_reset_service,
+            revoke_user_sessions_service,
+            delete_user_service,
+        },
+        audit::list_audit_events_service,
+        login_protection::unlock_account_service,
+    },
+    models::{ error_model::AppError, user_model::AccountStatus },
+    utils::{ auth_user::AuthUser, client_info::ClientInfo },
+    utils::form_data::{
+        AuditEventListQuery,
+        UnlockAccountForm,
+        UserAccessForm,
+        UserListQuery,
+        UserStatusForm,
+    },
+};
+use crate::AppState;
+
+// Admin routes are guarded by `RequireRole("admin")` in `route.rs`.
+
+pub async fn unlock_account_handler(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    Json(form): Json<UnlockAccountForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    unlock_account_service(State(app_state), admin, client, Json(form)).await
+}
+
+pub async fn set_user_access_handler(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    Path(user_id): Path<String>,
+    Json(form): Json<UserAccessForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    set_user_access_service(State(app_state), admin, client, user_id, Json(form)).await
+}
+
+pub async fn list_users_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(query): Query<UserListQuery>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    list_users_service(State(app_state), query).await
+}
+
+pub async fn get_user_handler(
+    State(app_state): State<Arc<AppState>>,
+    Path(user_id): Path<String>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    get_user_service(State(app_state), user_id).await
+}
+
+// The body is optional.
+pub async fn disable_user_handler(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    Path(user_id): Path<String>,
+    form: Option<Json<UserStatusForm>>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let reason = form.and_then(|Json(form)| form.reason);
+    let status = AccountStatus::SUSPENDED;
+    set_user_status_service(State(app_state), admin, client, user_id, status, reason).await
+}
+
+pub async fn enable_user_handler(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    Path(user_id): Path<String>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let status = AccountStatus::ACTIVE;
+    set_user_status_service(State(app_state), admin, client, user_id, status, None).await
+}
+
+pub async fn verify_user_handler(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    Path(user_id): Path<String>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    verify_user_service(State(app_state), admin, client, user_id).await
+}
+
+pub async fn force_password_reset_handler(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    Path(user_id): Path<String>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    force_password_reset_service(State(app_state), admin, client, user_id).await
+}
+
+pub async fn revoke_user_sessions_handler(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    Path(user_id): Path<String>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    revoke_user_sessions_service(State(app_state), admin, client, user_id).await
+}
+
+pub async fn delete_user_handler(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    Path(user_id): Path<String>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    delete_user_service(State(app_state), admin, client, user_id).await
+}
+
+pub async fn list_audit_events_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(query): Query<AuditEventListQuery>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    list_audit_events_service(State(app_state), query).await
+}
@@ -1 +1,4 @@
 pub mod user;
+pub mod admin;
+pub mod session;
+pub mod account;
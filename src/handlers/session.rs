@@ -0,0 +1,41 @@
+use std::sync::Arc;
+use axum::{ extract::{ Json, Path, Query }, http::StatusCode };
+use axum::extract::State;
+use serde_json::Value;
+
+use crate::{
+    services::session::{
+        list_sessions_service,
+        revoke_session_service,
+        revoke_all_sessions_service,
+    },
+    models::error_model::AppError,
+    utils::{ auth_user::AuthUser, client_info::ClientInfo, form_data::RevokeSessionsQuery },
+};
+use crate::AppState;
+
+pub async fn list_sessions_handler(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    list_sessions_service(State(app_state), auth_user).await
+}
+
+pub async fn revoke_session_handler(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    Path(session_id): Path<String>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    revoke_session_service(State(app_state), auth_user, client, session_id).await
+}
+
+pub async fn revoke_all_sessions_handler(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    Query(query): Query<RevokeSessionsQuery>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let keep_current = query.keep_current;
+    revoke_all_sessions_service(State(app_state), auth_user, client, keep_current).await
+}
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 use axum::{ extract::Json, http::{ StatusCode, HeaderMap }, response::IntoResponse };
 use axum::extract::State;
-use serde_json::{ json, Value };
+use serde_json::Value;
 
 use crate::{
     services::user::{
@@ -10,19 +10,31 @@ use crate::{
         logout_user_service,
         manual_login_user_service,
         account_verification_service,
+        reset_password_service,
     },
+    services::session::refresh_token_service,
+    models::error_model::AppError,
     utils::{
-        form_data::{ LoginForm, ManualLoginForm, VerificationCodeForm, RegisterForm, LogoutForm },
-        jwt::{ sign_jwt, get_token, validate_jwt },
+        form_data::{
+            LoginForm,
+            ManualLoginForm,
+            VerificationCodeForm,
+            RegisterForm,
+            LogoutForm,
+            ResetPasswordForm,
+        },
+        jwt::get_token,
+        client_info::ClientInfo,
     },
 };
 use crate::AppState;
 
 pub async fn register_user_handler(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<RegisterForm>
 ) -> impl IntoResponse {
-    let response = register_user_service(State(app_state), Json(form)).await;
+    let response = register_user_service(State(app_state), client, Json(form)).await;
     match response {
         Ok(data) => Ok(data),
         Err(err) => Err(err),
@@ -31,20 +43,30 @@ pub async fn register_user_handler(
 
 pub async fn account_verification_handler(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<VerificationCodeForm>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
-    let response = account_verification_service(State(app_state), Json(form)).await;
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let response = account_verification_service(State(app_state), client, Json(form)).await;
     match response {
         Ok(data) => Ok(data),
         Err(err) => Err(err),
     }
 }
 
+pub async fn reset_password_handler(
+    State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(form): Json<ResetPasswordForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    reset_password_service(State(app_state), client, Json(form)).await
+}
+
 pub async fn manual_login_user_handler(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<ManualLoginForm>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
-    let response = manual_login_user_service(State(app_state), Json(form)).await;
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let response = manual_login_user_service(State(app_state), client, Json(form)).await;
     match response {
         Ok(data) => Ok(data),
         Err(err) => Err(err),
@@ -53,9 +75,10 @@ pub async fn manual_login_user_handler(
 
 pub async fn login_google_user_handler(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<LoginForm>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
-    let response = login_google_user_service(State(app_state), Json(form)).await;
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let response = login_google_user_service(State(app_state), client, Json(form)).await;
     match response {
         Ok(data) => Ok(data),
         Err(err) => Err(err),
@@ -64,12 +87,13 @@ pub async fn login_google_user_handler(
 
 // flow:
 // this will delete the refresh token data in db
-// upon logout, this will not revoke the access token, the access token will wait for its expiry.
+// upon logout, access tokens of the session are rejected by AuthUser-protected routes right away.
 pub async fn logout_user_handler(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<LogoutForm>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
-    let response = logout_user_service(State(app_state), Json(form)).await;
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let response = logout_user_service(State(app_state), client, Json(form)).await;
     match response {
         Ok(data) => Ok(data),
         Err(err) => Err(err),
@@ -77,22 +101,13 @@ pub async fn logout_user_handler(
 }
 
 pub async fn refresh_token_handler(
+    State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     headers: HeaderMap
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
-    let auth_header = headers.get("Authorization").unwrap();
-    let refresh_token = get_token(auth_header);
-    let user_id = validate_jwt(&refresh_token.unwrap());
-    match user_id {
-        Ok(data) => {
-            let new_refresh_token = sign_jwt(&data, 1440)?;
-            let new_access_token = sign_jwt(&data, 5)?;
-            let data =
-                json!({
-                    "new_refresh_token": new_refresh_token,
-                    "new_access_token": new_access_token
-                });
-            Ok((StatusCode::OK, Json(data)))
-        }
-        Err(err) => Err(err),
-    }
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let auth_header = headers
+        .get("Authorization")
+        .ok_or(AppError::MissingAuthHeader)?;
+    let refresh_token = get_token(auth_header)?;
+    refresh_token_service(State(app_state), client, refresh_token).await
 }
@@ -1,9 +1,12 @@
-use crate::database::mongo::Mongo;
+use crate::config::config::Config;
+use crate::database::{ init_store, migrations, store::Store };
 use dotenv::dotenv;
-use axum::http::{ header::{ ACCEPT, AUTHORIZATION, CONTENT_TYPE }, HeaderValue, Method };
-use std::sync::Arc;
-use tower_http::cors::CorsLayer;
+use axum::http::{ header::{ ACCEPT, AUTHORIZATION, CONTENT_TYPE }, Method };
+use std::{ net::SocketAddr, sync::Arc, time::Duration };
+use tower_http::cors::{ AllowOrigin, CorsLayer };
+use rate_limit::RateLimiter;
 use route::create_router;
+use utils::hashing_pool::HashingPool;
 
 pub mod handlers;
 pub mod services;
@@ -11,28 +14,80 @@ pub mod database;
 pub mod models;
 pub mod config;
 pub mod utils;
+pub mod rate_limit;
 mod route;
+#[cfg(test)]
+mod test_support;
 
 pub struct AppState {
-    db: Mongo,
+    db: Box<dyn Store>,
+    // Loaded once at startup; nothing reads settings while serving requests.
+    config: Config,
+    hashing_pool: HashingPool,
+    rate_limiter: RateLimiter,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), ()> {
     dotenv().ok();
+    env_logger::init();
 
-    let db = Mongo::init();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("Invalid configuration:\n{}", err);
+            std::process::exit(1);
+        }
+    };
+    if config.google.is_none() {
+        log::info!("Google login is disabled, set GOOGLE_OAUTH_* to enable it");
+    }
+    if config.smtp.is_none() {
+        log::warn!(
+            "Email is disabled and manual registration with it, set SMTP_USERNAME and \
+            SMTP_PASSWORD to enable them"
+        );
+    }
+
+    let hashing_pool = HashingPool::new(
+        config.password_hash.max_concurrent_hashes,
+        Duration::from_millis(config.password_hash.hash_queue_timeout_ms)
+    );
+    let login_attempt_retention = Duration::from_secs(
+        config.login_protection.retention_secs() as u64
+    );
+    let db = match init_store(&config.database, &config.email, login_attempt_retention).await {
+        Ok(db) => db,
+        Err(err) => {
+            log::error!("Failed setting up the database: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if let Err(err) = migrations::start_legacy_sessions(db.as_ref(), &config.session).await {
+        log::error!("Failed setting an expiry on stored sessions: {}", err);
+        std::process::exit(1);
+    }
+    let rate_limiter = RateLimiter::init(&config.rate_limit).await.expect(
+        "Error Connecting to Rate Limit Store"
+    );
 
     let cors = CorsLayer::new()
-        .allow_origin("http://localhost:3000".parse::<HeaderValue>().unwrap())
-        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+        .allow_origin(AllowOrigin::list(config.server.cors_origins.clone()))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
         .allow_credentials(true)
         .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE]);
 
-    let app = create_router(Arc::new(AppState { db })).layer(cors);
+    let bind_address = config.server.bind_address;
+    let app_state = Arc::new(AppState { db, config, hashing_pool, rate_limiter });
+    tokio::spawn(services::session::purge_expired_sessions(app_state.clone()));
+    tokio::spawn(services::account::purge_deleted_accounts(app_state.clone()));
+    tokio::spawn(services::user::purge_expired_verification_codes(app_state.clone()));
+    tokio::spawn(services::login_protection::purge_stale_login_attempts(app_state.clone()));
+    let app = create_router(app_state).layer(cors);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(bind_address).await.unwrap();
+    // Connect info gives handlers the client address for per-IP login throttling.
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 
     Ok(())
 }
@@ -0,0 +1,145 @@
+use mongodb::bson::{ oid::ObjectId, DateTime };
+use serde::{ Serialize, Deserialize };
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AuditEventType {
+    REGISTER,
+    VERIFY_ACCOUNT,
+    LOGIN,
+    GOOGLE_LOGIN,
+    LOGOUT,
+    PASSWORD_RESET,
+    TOKEN_REFRESH,
+    SESSION_REVOKED,
+    DELETION_REQUESTED,
+    DELETION_CANCELLED,
+    ACCOUNT_DELETED,
+    // Admin actions. Verifications and session revocations by admins use the types above.
+    ACCESS_CHANGED,
+    ACCOUNT_DISABLED,
+    ACCOUNT_ENABLED,
+    PASSWORD_RESET_REQUIRED,
+    ACCOUNT_UNLOCKED,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AuditOutcome {
+    SUCCESS,
+    FAILURE,
+}
+
+// One security relevant action. Events are only ever added, never changed or removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub event_type: AuditEventType,
+    pub outcome: AuditOutcome,
+    // The error code of a failure, or the reason an admin gave.
+    pub reason: Option<String>,
+    // The account acted on: the one the email belongs to, if there is one.
+    pub user_id: Option<ObjectId>,
+    // Who acted, when known: the user themselves once signed in, or an admin. None for the
+    // service and for requests that aren't signed in, like logins.
+    #[serde(default)]
+    pub actor_id: Option<ObjectId>,
+    pub email: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime,
+}
+
+impl AuditEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::REGISTER => "REGISTER",
+            AuditEventType::VERIFY_ACCOUNT => "VERIFY_ACCOUNT",
+            AuditEventType::LOGIN => "LOGIN",
+            AuditEventType::GOOGLE_LOGIN => "GOOGLE_LOGIN",
+            AuditEventType::LOGOUT => "LOGOUT",
+            AuditEventType::PASSWORD_RESET => "PASSWORD_RESET",
+            AuditEventType::TOKEN_REFRESH => "TOKEN_REFRESH",
+            AuditEventType::SESSION_REVOKED => "SESSION_REVOKED",
+            AuditEventType::DELETION_REQUESTED => "DELETION_REQUESTED",
+            AuditEventType::DELETION_CANCELLED => "DELETION_CANCELLED",
+            AuditEventType::ACCOUNT_DELETED => "ACCOUNT_DELETED",
+            AuditEventType::ACCESS_CHANGED => "ACCESS_CHANGED",
+            AuditEventType::ACCOUNT_DISABLED => "ACCOUNT_DISABLED",
+            AuditEventType::ACCOUNT_ENABLED => "ACCOUNT_ENABLED",
+            AuditEventType::PASSWORD_RESET_REQUIRED => "PASSWORD_RESET_REQUIRED",
+            AuditEventType::ACCOUNT_UNLOCKED => "ACCOUNT_UNLOCKED",
+        }
+    }
+
+    pub fn from_stored(event_type: &str) -> Option<AuditEventType> {
+        match event_type {
+            "REGISTER" => Some(AuditEventType::REGISTER),
+            "VERIFY_ACCOUNT" => Some(AuditEventType::VERIFY_ACCOUNT),
+            "LOGIN" => Some(AuditEventType::LOGIN),
+            "GOOGLE_LOGIN" => Some(AuditEventType::GOOGLE_LOGIN),
+            "LOGOUT" => Some(AuditEventType::LOGOUT),
+            "PASSWORD_RESET" => Some(AuditEventType::PASSWORD_RESET),
+            "TOKEN_REFRESH" => Some(AuditEventType::TOKEN_REFRESH),
+            "SESSION_REVOKED" => Some(AuditEventType::SESSION_REVOKED),
+            "DELETION_REQUESTED" => Some(AuditEventType::DELETION_REQUESTED),
+            "DELETION_CANCELLED" => Some(AuditEventType::DELETION_CANCELLED),
+            "ACCOUNT_DELETED" => Some(AuditEventType::ACCOUNT_DELETED),
+            "ACCESS_CHANGED" => Some(AuditEventType::ACCESS_CHANGED),
+            "ACCOUNT_DISABLED" => Some(AuditEventType::ACCOUNT_DISABLED),
+            "ACCOUNT_ENABLED" => Some(AuditEventType::ACCOUNT_ENABLED),
+            "PASSWORD_RESET_REQUIRED" => Some(AuditEventType::PASSWORD_RESET_REQUIRED),
+            "ACCOUNT_UNLOCKED" => Some(AuditEventType::ACCOUNT_UNLOCKED),
+            _ => None,
+        }
+    }
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::SUCCESS => "SUCCESS",
+            AuditOutcome::FAILURE => "FAILURE",
+        }
+    }
+
+    pub fn from_stored(outcome: &str) -> Option<AuditOutcome> {
+        match outcome {
+            "SUCCESS" => Some(AuditOutcome::SUCCESS),
+            "FAILURE" => Some(AuditOutcome::FAILURE),
+            _ => None,
+        }
+    }
+}
+
+// What the API shows of an event.
+#[derive(Debug, Serialize)]
+pub struct AuditEventView {
+    pub id: Option<String>,
+    pub event_type: &'static str,
+    pub outcome: &'static str,
+    pub reason: Option<String>,
+    pub user_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub email: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: Option<String>,
+}
+
+impl From<&AuditEvent> for AuditEventView {
+    fn from(event: &AuditEvent) -> Self {
+        AuditEventView {
+            id: event.id.map(|id| id.to_hex()),
+            event_type: event.event_type.as_str(),
+            outcome: event.outcome.as_str(),
+            reason: event.reason.clone(),
+            user_id: event.user_id.map(|id| id.to_hex()),
+            actor_id: event.actor_id.map(|id| id.to_hex()),
+            email: event.email.clone(),
+            ip: event.ip.clone(),
+            user_agent: event.user_agent.clone(),
+            created_at: event.created_at.try_to_rfc3339_string().ok(),
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::fmt;
+
+use axum::{
+    http::{ header::RETRY_AFTER, HeaderValue, StatusCode },
+    response::{ IntoResponse, Response },
+};
+use mongodb::bson::DateTime;
+use serde_json::{ json, Value };
+
+use crate::database::error::StoreError;
+use crate::models::response_model::ResponseBuilder;
+use crate::utils::password_policy::PasswordViolation;
+use crate::utils::password_hasher::{ HashError, BCRYPT_MAX_PASSWORD_BYTES };
+
+// Every error the API can return. `code` is stable and meant for clients to branch on;
+// `message` is for humans and may change.
+#[derive(Debug)]
+pub enum AppError {
+    EmailRequired,
+    InvalidEmail,
+    EmailDomainNotAllowed,
+    DisposableEmail,
+    PasswordRequired,
+    WeakPassword(Vec<PasswordViolation>),
+    EmailAlreadyExists,
+    WrongEmail,
+    WrongPassword,
+    // Replaces WrongEmail and WrongPassword in hardened mode.
+    InvalidCredentials,
+    AccountNotVerified,
+    AccountSuspended,
+    AccountPendingDeletion,
+    PasswordResetRequired,
+    AccountLocked(DateTime),
+    TooManyAttempts(DateTime),
+    // Seconds until the client may retry.
+    RateLimited(u64),
+    InvalidVerificationCode,
+    MissingAuthHeader,
+    InvalidAuthHeader,
+    InvalidToken,
+    ExpiredToken,
+    // A Google ID token that isn't valid for this app.
+    InvalidIdToken,
+    InvalidId,
+    // A role or permission name that can't be stored.
+    InvalidGrantName(String),
+    InvalidQuery(String),
+    Forbidden,
+    // Admins can't suspend, delete or change the access of their own account here.
+    OwnAccount,
+    // The change would leave no active admin.
+    LastAdmin,
+    UserNotFound,
+    SessionNotFound,
+    EmailDelivery(String),
+    // Google couldn't be asked whether an ID token is valid.
+    IdentityProvider(String),
+    // An optional feature that isn't configured on this server.
+    FeatureDisabled(&'static str),
+    Overloaded,
+    Storage(StoreError),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::EmailRequired => "EMAIL_REQUIRED",
+            AppError::InvalidEmail => "INVALID_EMAIL",
+            AppError::EmailDomainNotAllowed => "EMAIL_DOMAIN_NOT_ALLOWED",
+            AppError::DisposableEmail => "DISPOSABLE_EMAIL",
+            AppError::PasswordRequired => "PASSWORD_REQUIRED",
+            AppError::WeakPassword(_) => "WEAK_PASSWORD",
+            AppError::EmailAlreadyExists => "EMAIL_ALREADY_EXISTS",
+            AppError::WrongEmail => "WRONG_EMAIL",
+            AppError::WrongPassword => "WRONG_PASSWORD",
+            AppError::InvalidCredentials => "INVALID_CREDENTIALS",
+            AppError::AccountNotVerified => "ACCOUNT_NOT_VERIFIED",
+            AppError::AccountSuspended => "ACCOUNT_SUSPENDED",
+            AppError::AccountPendingDeletion => "ACCOUNT_PENDING_DELETION",
+            AppError::PasswordResetRequired => "PASSWORD_RESET_REQUIRED",
+            AppError::AccountLocked(_) => "ACCOUNT_LOCKED",
+            AppError::TooManyAttempts(_) => "TOO_MANY_ATTEMPTS",
+            AppError::RateLimited(_) => "RATE_LIMITED",
+            AppError::InvalidVerificationCode => "INVALID_VERIFICATION_CODE",
+            AppError::MissingAuthHeader => "MISSING_AUTH_HEADER",
+            AppError::InvalidAuthHeader => "INVALID_AUTH_HEADER",
+            AppError::InvalidToken => "INVALID_TOKEN",
+            AppError::ExpiredToken => "TOKEN_EXPIRED",
+            AppError::InvalidIdToken => "INVALID_ID_TOKEN",
+            AppError::InvalidId => "INVALID_ID",
+            AppError::InvalidGrantName(_) => "INVALID_GRANT_NAME",
+            AppError::InvalidQuery(_) => "INVALID_QUERY",
+            AppError::Forbidden => "FORBIDDEN",
+            AppError::OwnAccount => "OWN_ACCOUNT",
+            AppError::LastAdmin => "LAST_ADMIN",
+            AppError::UserNotFound => "USER_NOT_FOUND",
+            AppError::SessionNotFound => "SESSION_NOT_FOUND",
+            AppError::EmailDelivery(_) => "EMAIL_DELIVERY_FAILED",
+            AppError::IdentityProvider(_) => "IDENTITY_PROVIDER_UNAVAILABLE",
+            AppError::FeatureDisabled(_) => "FEATURE_DISABLED",
+            AppError::Overloaded => "SERVER_BUSY",
+            AppError::Storage(StoreError::Unavailable(_)) => "DATABASE_UNAVAILABLE",
+            AppError::Storage(StoreError::Duplicate(_)) => "CONFLICT",
+            AppError::Storage(_) => "DATABASE_ERROR",
+            AppError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            | AppError::EmailRequired
+            | AppError::InvalidEmail
+            | AppError::EmailDomainNotAllowed
+            | AppError::DisposableEmail
+            | AppError::PasswordRequired
+            | AppError::WeakPassword(_)
+            | AppError::WrongEmail
+            | AppError::WrongPassword
+            | AppError::InvalidCredentials
+            | AppError::InvalidVerificationCode
+            | AppError::MissingAuthHeader
+            | AppError::InvalidAuthHeader
+            | AppError::InvalidId
+            | AppError::InvalidGrantName(_)
+            | AppError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            | AppError::InvalidToken
+            | AppError::ExpiredToken
+            | AppError::InvalidIdToken => StatusCode::UNAUTHORIZED,
+            | AppError::AccountNotVerified
+            | AppError::AccountSuspended
+            | AppError::AccountPendingDeletion
+            | AppError::PasswordResetRequired
+            | AppError::Forbidden => StatusCode::FORBIDDEN,
+            | AppError::AccountLocked(_)
+            | AppError::TooManyAttempts(_)
+            | AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::UserNotFound | AppError::SessionNotFound => StatusCode::NOT_FOUND,
+            | AppError::EmailAlreadyExists
+            | AppError::OwnAccount
+            | AppError::LastAdmin => StatusCode::CONFLICT,
+            AppError::EmailDelivery(_) | AppError::IdentityProvider(_) => StatusCode::BAD_GATEWAY,
+            AppError::FeatureDisabled(_) => StatusCode::NOT_IMPLEMENTED,
+            AppError::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Storage(err) => err.status_code(),
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            AppError::EmailRequired => "Email is required.".to_string(),
+            AppError::InvalidEmail => "Invalid email format.".to_string(),
+            AppError::EmailDomainNotAllowed =>
+                "Emails from this domain are not allowed.".to_string(),
+            AppError::DisposableEmail =>
+                "Disposable email addresses are not allowed.".to_string(),
+            AppError::PasswordRequired => "Password is required.".to_string(),
+            AppError::WeakPassword(violations) =>
+                match violations.as_slice() {
+                    [violation] => violation.message(),
+                    _ => "Password does not meet the requirements.".to_string(),
+                }
+            AppError::EmailAlreadyExists => "Email already exist.".to_string(),
+            AppError::WrongEmail => "Wrong email.".to_string(),
+            AppError::WrongPassword => "Wrong password.".to_string(),
+            AppError::InvalidCredentials => "Invalid email or password.".to_string(),
+            AppError::AccountNotVerified =>
+                "Verify your account first. We've sent a code to your email.".to_string(),
+            AppError::AccountSuspended => "This account has been suspended.".to_string(),
+            AppError::AccountPendingDeletion =>
+                "This account is scheduled for deletion.".to_string(),
+            AppError::PasswordResetRequired =>
+                "You must reset your password. We've sent a code to your email.".to_string(),
+            AppError::AccountLocked(_) =>
+                "Too many failed login attempts. The account is temporarily locked.".to_string(),
+            AppError::TooManyAttempts(_) =>
+                "Too many failed login attempts. Please try again later.".to_string(),
+            AppError::RateLimited(_) =>
+                "Too many requests. Please slow down and try again later.".to_string(),
+            AppError::InvalidVerificationCode => "Wrong code. Please try again.".to_string(),
+            AppError::MissingAuthHeader => "No auth header.".to_string(),
+            AppError::InvalidAuthHeader => "Invalid auth header format.".to_string(),
+            AppError::InvalidToken => "Invalid access token.".to_string(),
+            AppError::ExpiredToken => "Expired access token.".to_string(),
+            AppError::InvalidIdToken => "Invalid Google ID token.".to_string(),
+            AppError::InvalidId => "Invalid ID format.".to_string(),
+            AppError::InvalidGrantName(name) =>
+                format!("Invalid role or permission name: {:?}.", name),
+            AppError::InvalidQuery(message) => format!("Invalid query: {}.", message),
+            AppError::Forbidden => "You are not allowed to do this.".to_string(),
+            AppError::OwnAccount =>
+                "Admins can't change their own account. Ask another admin.".to_string(),
+            AppError::LastAdmin => "This would leave no active admin.".to_string(),
+            AppError::UserNotFound => "User does not exist.".to_string(),
+            AppError::SessionNotFound => "Session does not exist.".to_string(),
+            AppError::EmailDelivery(_) =>
+                "Failed sending email. Please try again later.".to_string(),
+            AppError::IdentityProvider(_) =>
+                "Failed verifying the Google sign-in. Please try again later.".to_string(),
+            AppError::FeatureDisabled(feature) =>
+                format!("{} is not available on this server.", feature),
+            AppError::Overloaded | AppError::Storage(StoreError::Unavailable(_)) =>
+                "Service temporarily unavailable. Please try again later.".to_string(),
+            AppError::Storage(StoreError::Duplicate(_)) => "Record already exists.".to_string(),
+            AppError::Storage(_) | AppError::Internal(_) =>
+                "Something went wrong. Please try again later.".to_string(),
+        }
+    }
+}
+
+impl AppError {
+    // Structured information for errors that carry more than a code and a message.
+    pub fn details(&self) -> Option<Value> {
+        match self {
+            AppError::WeakPassword(violations) => {
+                let details: Vec<Value> = violations
+                    .iter()
+                    .map(|violation| {
+                        let mut detail = json!(violation);
+                        detail["message"] = json!(violation.message());
+                        detail
+                    })
+                    .collect();
+                Some(json!(details))
+            }
+            AppError::AccountLocked(until) | AppError::TooManyAttempts(until) =>
+                Some(
+                    json!({
+                    "locked_until": until.try_to_rfc3339_string().ok(),
+                    "retry_after_seconds": retry_after_secs(*until),
+                })
+                ),
+            AppError::RateLimited(secs) => Some(json!({ "retry_after_seconds": secs })),
+            _ => None,
+        }
+    }
+}
+
+fn retry_after_secs(until: DateTime) -> u64 {
+    let millis = until.timestamp_millis() - DateTime::now().timestamp_millis();
+    (millis.max(0) as u64).div_ceil(1000)
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Storage(err) => write!(f, "{}", err),
+            | AppError::EmailDelivery(err)
+            | AppError::IdentityProvider(err)
+            | AppError::Internal(err) => write!(f, "{}", err),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<StoreError> for AppError {
+    fn from(err: StoreError) -> Self {
+        AppError::Storage(err)
+    }
+}
+
+impl From<HashError> for AppError {
+    fn from(err: HashError) -> Self {
+        match err {
+            HashError::PasswordTooLong =>
+                AppError::WeakPassword(
+                    vec![PasswordViolation::TooLong { max: BCRYPT_MAX_PASSWORD_BYTES }]
+                ),
+            err => AppError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        if self.status_code().is_server_error() {
+            log::error!("{}", self);
+        }
+        let message = self.message();
+        let mut response = ResponseBuilder::<Value>
+            ::new(self.status_code())
+            .message(&message)
+            .error(self.code(), &message);
+        if let Some(details) = self.details() {
+            response = response.error_details(details);
+        }
+        let mut response = response.build().into_response();
+        let retry_after = match self {
+            AppError::Overloaded => Some(1),
+            AppError::AccountLocked(until) | AppError::TooManyAttempts(until) => {
+                Some(retry_after_secs(until))
+            }
+            AppError::RateLimited(secs) => Some(secs),
+            _ => None,
+        };
+        if let Some(secs) = retry_after {
+            response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
@@ -0,0 +1,21 @@
+use mongodb::bson::DateTime;
+use serde::{ Serialize, Deserialize };
+
+// Failed login counter for one key: `account:<email>` or `ip:<address>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginAttempt {
+    pub key: String,
+    pub failures: i64,
+    pub last_failure_at: DateTime,
+    pub locked_until: Option<DateTime>,
+}
+
+impl LoginAttempt {
+    pub fn account_key(email: &str) -> String {
+        format!("account:{}", email)
+    }
+
+    pub fn ip_key(ip: &str) -> String {
+        format!("ip:{}", ip)
+    }
+}
@@ -1,3 +1,6 @@
 pub mod user_model;
 pub mod refresh_token_model;
 pub mod response_model;
+pub mod error_model;
+pub mod login_attempt_model;
+pub mod audit_event_model;
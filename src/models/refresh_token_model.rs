@@ -1,13 +1,26 @@
 use serde::{ Serialize, Deserialize };
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{ oid::ObjectId, DateTime };
 
 use super::user_model::Email;
 
-#[derive(Debug, Serialize, Deserialize)]
+// One login session. The id is the session id shown to users; the token is rotated on refresh.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RefreshToken {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub user_id: Option<ObjectId>,
     pub email: Email,
     pub refresh_token: String,
+    // Device info, updated on every refresh. Missing on tokens stored before sessions existed.
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub ip: Option<String>,
+    #[serde(default)]
+    pub session_started_at: Option<DateTime>,
+    #[serde(default)]
+    pub last_used_at: Option<DateTime>,
+    // When the session ends unless refreshed. Mongo deletes the document at this time.
+    #[serde(default)]
+    pub expires_at: Option<DateTime>,
 }
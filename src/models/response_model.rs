@@ -64,8 +64,9 @@ impl<T: Serialize> ResponseBuilder<T> {
         self
     }
 
-    pub fn error(mut self, message: &str) -> Self {
+    pub fn error(mut self, code: &str, message: &str) -> Self {
         self.error = Some(json!({
+            "code": code,
             "message": message
         }));
         self.data = None;
@@ -73,6 +74,13 @@ impl<T: Serialize> ResponseBuilder<T> {
         self
     }
 
+    pub fn error_details(mut self, details: Value) -> Self {
+        if let Some(error) = self.error.as_mut() {
+            error["details"] = details;
+        }
+        self
+    }
+
     pub fn meta(mut self, meta: Value) -> Self {
         self.meta = Some(meta);
         self
@@ -80,6 +88,6 @@ impl<T: Serialize> ResponseBuilder<T> {
 
     pub fn build(self) -> (StatusCode, Json<Value>) {
         let obj = json!(self);
-        return (self.response_type, Json(obj));
+        (self.response_type, Json(obj))
     }
 }
@@ -1,14 +1,22 @@
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{ oid::ObjectId, DateTime };
 use serde::{ Serialize, Deserialize };
-use bcrypt::{ hash_with_result, BcryptError };
-use regex::Regex;
-use axum::{ http::StatusCode };
-use crate::services::user::error_response;
-use axum::{ extract::Json };
+use crate::models::error_model::AppError;
+use crate::config::config::{ EmailConfig, PasswordPolicy, PasswordHashConfig };
+use crate::utils::{
+    email_validator,
+    jwt::Grants,
+    password_policy,
+    password_hasher::{ self, HashError },
+};
+
+// Given to every new account.
+pub const USER_ROLE: &str = "user";
+// Required by the /admin endpoints.
+pub const ADMIN_ROLE: &str = "admin";
 
 #[allow(non_snake_case)]
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
@@ -17,6 +25,24 @@ pub struct User {
     pub password: Option<Password>,
     pub is_verified: Option<bool>,
     pub login_type: LoginTypes,
+    // Copied into access tokens, so other services can authorize without asking us.
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    #[serde(default)]
+    pub status: AccountStatus,
+    // Why the status was last changed, e.g. an admin's note when suspending.
+    #[serde(default)]
+    pub status_reason: Option<String>,
+    #[serde(default)]
+    pub status_changed_at: Option<DateTime>,
+    // The status before the last change, so a cancelled deletion can put it back.
+    #[serde(default)]
+    pub previous_status: Option<AccountStatus>,
+    // Set by an admin; password login is refused until the user picks a new password.
+    #[serde(default)]
+    pub password_reset_required: bool,
 }
 
 #[derive(Debug)]
@@ -27,12 +53,38 @@ pub struct UserBuilder {
     password: Option<Password>,
     is_verified: Option<bool>,
     login_type: LoginTypes,
+    roles: Vec<String>,
+    permissions: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// What an emailed code is for. Codes only work for their own purpose, so e.g. a short
+// verification code can't reset a password.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CodePurpose {
+    ACCOUNT_VERIFICATION,
+    PASSWORD_RESET,
+    DELETION_CANCEL,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserVerificationCode {
     pub email: Email,
     pub code: String,
+    pub purpose: CodePurpose,
+    // Mongo deletes the code at this time, the other backends on the next purge.
+    pub expires_at: DateTime,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum AccountStatus {
+    #[default]
+    ACTIVE,
+    // Blocked by an admin. The account and its data are kept.
+    SUSPENDED,
+    // The user asked for the account to be deleted; it is blocked until then.
+    PENDING_DELETION,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,6 +100,121 @@ pub struct Email(String);
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Password(String);
 
+impl LoginTypes {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginTypes::GOOGLE => "GOOGLE",
+            LoginTypes::FACEBOOK => "FACEBOOK",
+            LoginTypes::MANUAL => "MANUAL",
+        }
+    }
+
+    pub fn from_stored(login_type: &str) -> Option<LoginTypes> {
+        match login_type {
+            "GOOGLE" => Some(LoginTypes::GOOGLE),
+            "FACEBOOK" => Some(LoginTypes::FACEBOOK),
+            "MANUAL" => Some(LoginTypes::MANUAL),
+            _ => None,
+        }
+    }
+}
+
+impl CodePurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CodePurpose::ACCOUNT_VERIFICATION => "ACCOUNT_VERIFICATION",
+            CodePurpose::PASSWORD_RESET => "PASSWORD_RESET",
+            CodePurpose::DELETION_CANCEL => "DELETION_CANCEL",
+        }
+    }
+
+    pub fn from_stored(purpose: &str) -> Option<CodePurpose> {
+        match purpose {
+            "ACCOUNT_VERIFICATION" => Some(CodePurpose::ACCOUNT_VERIFICATION),
+            "PASSWORD_RESET" => Some(CodePurpose::PASSWORD_RESET),
+            "DELETION_CANCEL" => Some(CodePurpose::DELETION_CANCEL),
+            _ => None,
+        }
+    }
+}
+
+impl AccountStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountStatus::ACTIVE => "ACTIVE",
+            AccountStatus::SUSPENDED => "SUSPENDED",
+            AccountStatus::PENDING_DELETION => "PENDING_DELETION",
+        }
+    }
+
+    pub fn from_stored(status: &str) -> Option<AccountStatus> {
+        match status {
+            "ACTIVE" => Some(AccountStatus::ACTIVE),
+            "SUSPENDED" => Some(AccountStatus::SUSPENDED),
+            "PENDING_DELETION" => Some(AccountStatus::PENDING_DELETION),
+            _ => None,
+        }
+    }
+}
+
+impl User {
+    pub fn grants(&self) -> Grants {
+        Grants { roles: self.roles.clone(), permissions: self.permissions.clone() }
+    }
+
+    // Whether the account may log in or refresh its session.
+    pub fn check_status(&self) -> Result<(), AppError> {
+        match self.status {
+            AccountStatus::ACTIVE => Ok(()),
+            AccountStatus::SUSPENDED => Err(AppError::AccountSuspended),
+            AccountStatus::PENDING_DELETION => Err(AppError::AccountPendingDeletion),
+        }
+    }
+
+    // Ids are ObjectIds, which start with their creation time.
+    pub fn created_at(&self) -> Option<DateTime> {
+        self.id.map(|id| id.timestamp())
+    }
+}
+
+// What the API shows of a user. Leaves out the password hash.
+#[derive(Debug, Serialize)]
+pub struct UserView {
+    pub id: Option<String>,
+    pub name: String,
+    pub email: String,
+    pub is_verified: bool,
+    pub login_type: &'static str,
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+    pub status: &'static str,
+    pub status_reason: Option<String>,
+    pub status_changed_at: Option<String>,
+    pub password_reset_required: bool,
+    pub created_at: Option<String>,
+}
+
+impl From<&User> for UserView {
+    fn from(user: &User) -> Self {
+        UserView {
+            id: user.id.map(|id| id.to_hex()),
+            name: user.name.clone(),
+            email: user.email.as_str().clone(),
+            is_verified: user.is_verified.unwrap_or_default(),
+            login_type: user.login_type.as_str(),
+            roles: user.roles.clone(),
+            permissions: user.permissions.clone(),
+            status: user.status.as_str(),
+            status_reason: user.status_reason.clone(),
+            status_changed_at: user.status_changed_at.and_then(|time| {
+                time.try_to_rfc3339_string().ok()
+            }),
+            password_reset_required: user.password_reset_required,
+            created_at: user.created_at().and_then(|time| time.try_to_rfc3339_string().ok()),
+        }
+    }
+}
+
 impl UserBuilder {
     pub fn new(name: String, email: Email, login_type: LoginTypes) -> Self {
         Self {
@@ -57,6 +224,8 @@ impl UserBuilder {
             password: None,
             is_verified: None,
             login_type,
+            roles: vec![USER_ROLE.to_string()],
+            permissions: vec![],
         }
     }
 
@@ -75,6 +244,16 @@ impl UserBuilder {
         self
     }
 
+    pub fn roles(mut self, roles: Vec<String>) -> Self {
+        self.roles = roles;
+        self
+    }
+
+    pub fn permissions(mut self, permissions: Vec<String>) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
     pub fn build(self) -> User {
         User {
             id: self.id,
@@ -83,22 +262,82 @@ impl UserBuilder {
             password: self.password,
             is_verified: self.is_verified,
             login_type: self.login_type,
+            roles: self.roles,
+            permissions: self.permissions,
+            status: AccountStatus::ACTIVE,
+            status_reason: None,
+            status_changed_at: None,
+            previous_status: None,
+            password_reset_required: false,
         }
     }
 }
 
 impl Email {
-    pub fn parse(email: String) -> Result<Email, (StatusCode, Json<serde_json::Value>)> {
+    pub fn parse(email: String, config: &EmailConfig) -> Result<Email, AppError> {
+        let email = Email::normalize(&email, config);
         if email.is_empty() {
-            return Err(error_response("Email is required.", StatusCode::BAD_REQUEST));
+            return Err(AppError::EmailRequired);
         }
-        let email_regex = Regex::new(r"^[\w-]+(\.[\w-]+)*@([\w-]+\.)+[a-zA-Z]{2,7}$").unwrap();
-        if !email_regex.is_match(&email) {
-            return Err(error_response("Invalid email format.", StatusCode::BAD_REQUEST));
+        if !email_validator::is_valid(&email) {
+            return Err(AppError::InvalidEmail);
         }
         Ok(Email(email))
     }
 
+    // Operator domain rules. Only checked when an account is created so that tightening the
+    // rules never locks out existing users.
+    pub fn check_domain(&self, config: &EmailConfig) -> Result<(), AppError> {
+        let domain = self.domain();
+        if
+            email_validator::domain_in(domain, &config.denied_domains) ||
+            (!config.allowed_domains.is_empty() &&
+                !email_validator::domain_in(domain, &config.allowed_domains))
+        {
+            return Err(AppError::EmailDomainNotAllowed);
+        }
+        if
+            config.reject_disposable &&
+            email_validator::domain_in(domain, &config.disposable_domains)
+        {
+            return Err(AppError::DisposableEmail);
+        }
+        Ok(())
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.0.rsplit_once('@').map(|(local, _)| local).unwrap_or(&self.0)
+    }
+
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default()
+    }
+
+    // Canonical form used for storage and lookups: trimmed, domain lowercased and converted to
+    // its ASCII (punycode) form and, unless disabled, the local part lowercased too.
+    pub fn normalize(email: &str, config: &EmailConfig) -> String {
+        let email = email.trim();
+        match email.rsplit_once('@') {
+            Some((local, domain)) => {
+                let local = if config.lowercase_local_part {
+                    local.to_lowercase()
+                } else {
+                    local.to_string()
+                };
+                let domain = idna
+                    ::domain_to_ascii(domain)
+                    .unwrap_or_else(|_| domain.to_lowercase());
+                format!("{}@{}", local, domain)
+            }
+            None => email.to_string(),
+        }
+    }
+
+    // Wraps a value read back from storage, which was already parsed when it was saved.
+    pub(crate) fn from_stored(email: String) -> Email {
+        Email(email)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
@@ -109,40 +348,45 @@ impl Email {
 }
 
 impl Password {
-    pub fn parse(password: String) -> Result<Password, (StatusCode, Json<serde_json::Value>)> {
+    // Checks a new password against the configured policy. `context` is user data the password
+    // must not contain, such as the name and email.
+    pub fn parse(
+        password: String,
+        policy: &PasswordPolicy,
+        context: &[&str]
+    ) -> Result<Password, AppError> {
+        if password.is_empty() {
+            return Err(AppError::PasswordRequired);
+        }
+        let violations = password_policy::validate(&password, policy, context);
+        if !violations.is_empty() {
+            return Err(AppError::WeakPassword(violations));
+        }
+        Ok(Password(password))
+    }
+
+    // A password typed at login. Only presence is checked: the policy applies to new passwords,
+    // and changing it must not lock out existing users.
+    pub fn parse_login(password: String) -> Result<Password, AppError> {
         if password.is_empty() {
-            return Err(error_response("Password is required.", StatusCode::BAD_REQUEST));
-        }
-        if password.len() < 6 {
-            return Err(
-                error_response(
-                    "Password must be at least 6 characters long.",
-                    StatusCode::BAD_REQUEST
-                )
-            );
-        }
-        if !password.chars().any(|c| c.is_digit(10)) {
-            return Err(
-                error_response(
-                    "Password must contain at least one number.",
-                    StatusCode::BAD_REQUEST
-                )
-            );
-        }
-        if !password.chars().any(|c| c.is_ascii_punctuation()) {
-            return Err(
-                error_response(
-                    "Password must contain at least one special character.",
-                    StatusCode::BAD_REQUEST
-                )
-            );
+            return Err(AppError::PasswordRequired);
         }
         Ok(Password(password))
     }
 
-    pub fn hash(&self) -> Result<Password, BcryptError> {
-        let hashed_password = hash_with_result(&self.0, 12)?;
-        Ok(Password(hashed_password.to_string()))
+    pub fn hash(&self, config: &PasswordHashConfig) -> Result<Password, HashError> {
+        let hashed_password = password_hasher::hash(&self.0, config)?;
+        Ok(Password(hashed_password))
+    }
+
+    // `self` is the plain password, `hashed` the stored hash.
+    pub fn verify(&self, hashed: &Password) -> Result<bool, HashError> {
+        password_hasher::verify(&self.0, &hashed.0)
+    }
+
+    // Wraps a hash read back from storage.
+    pub(crate) fn from_stored(password: String) -> Password {
+        Password(password)
     }
 
     pub fn is_empty(&self) -> bool {
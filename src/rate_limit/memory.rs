@@ -0,0 +1,77 @@
+use std::{ collections::HashMap, sync::Mutex };
+
+use async_trait::async_trait;
+use mongodb::bson::DateTime;
+
+use crate::database::error::StoreError;
+use super::{ Bucket, RateLimitDecision, RateLimitRule, RateLimitStore };
+
+// Above this many buckets, full ones are dropped since they hold nothing worth remembering.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// Buckets live in process memory, so every instance of the service limits on its own.
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    // Each bucket with the time, in millis, at which it is full again.
+    map: HashMap<String, (Bucket, i64)>,
+    // The next prune happens once the map grows past this. It is set to twice the size left
+    // after a prune, so the cost of pruning is spread over as many requests as it scanned.
+    prune_above: usize,
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn take(
+        &self,
+        key: &str,
+        rule: &RateLimitRule,
+        now: DateTime
+    ) -> Result<RateLimitDecision, StoreError> {
+        let now = now.timestamp_millis();
+        let mut buckets = self.buckets.lock()?;
+        if buckets.map.len() > buckets.prune_above.max(PRUNE_THRESHOLD) {
+            buckets.map.retain(|_, (_, full_at)| *full_at > now);
+            buckets.prune_above = buckets.map.len() * 2;
+        }
+        let current = buckets.map.get(key).map(|(bucket, _)| *bucket);
+        let (bucket, decision) = Bucket::take(current, rule, now);
+        buckets.map.insert(key.to_string(), (bucket, now + bucket.full_after(rule)));
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULE: RateLimitRule = RateLimitRule::per_ip("test", 2, 60);
+
+    #[tokio::test]
+    async fn keeps_a_bucket_per_key() {
+        let store = MemoryRateLimitStore::default();
+        let now = DateTime::from_millis(0);
+        for _ in 0..2 {
+            assert!(store.take("ip:a", &RULE, now).await.unwrap().allowed);
+        }
+        assert!(!store.take("ip:a", &RULE, now).await.unwrap().allowed);
+        assert!(store.take("ip:b", &RULE, now).await.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn prunes_full_buckets_past_the_threshold() {
+        let store = MemoryRateLimitStore::default();
+        for i in 0..=PRUNE_THRESHOLD {
+            store.take(&format!("ip:{}", i), &RULE, DateTime::from_millis(0)).await.unwrap();
+        }
+        // Every bucket above is full again by then, so all but the new one are dropped.
+        let later = DateTime::from_millis(60_000);
+        store.take("ip:new", &RULE, later).await.unwrap();
+        store.take("ip:newer", &RULE, later).await.unwrap();
+        assert_eq!(store.buckets.lock().unwrap().map.len(), 2);
+    }
+}
@@ -0,0 +1,320 @@
+pub mod memory;
+#[cfg(feature = "redis")]
+pub mod redis;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    body::{ self, Body },
+    extract::{ FromRequestParts, Request, State },
+    http::{ header::AUTHORIZATION, HeaderMap, HeaderValue, StatusCode },
+    middleware::Next,
+    response::{ IntoResponse, Response },
+};
+use mongodb::bson::DateTime;
+use serde_json::Value;
+
+use crate::AppState;
+use crate::config::config::RateLimitConfig;
+use crate::database::error::StoreError;
+use crate::models::{ error_model::AppError, user_model::Email };
+use crate::utils::{ client_info::ClientInfo, jwt::{ decode_jwt, get_token, TokenType } };
+use self::memory::MemoryRateLimitStore;
+
+// Same as axum's default limit for `Json` bodies.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitKey {
+    Ip,
+    // The normalized `email` field of the JSON body.
+    Email,
+    // The user in a valid bearer access token.
+    UserId,
+    // The user in a valid bearer refresh token, for the refresh route.
+    RefreshUserId,
+}
+
+// A token bucket holding `capacity` requests that refills completely over `period_secs`.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitRule {
+    pub name: &'static str,
+    pub key: RateLimitKey,
+    pub capacity: u32,
+    pub period_secs: u64,
+}
+
+impl RateLimitRule {
+    pub const fn per_ip(name: &'static str, capacity: u32, period_secs: u64) -> Self {
+        RateLimitRule { name, key: RateLimitKey::Ip, capacity, period_secs }
+    }
+
+    pub const fn per_email(name: &'static str, capacity: u32, period_secs: u64) -> Self {
+        RateLimitRule { name, key: RateLimitKey::Email, capacity, period_secs }
+    }
+
+    pub const fn per_user(name: &'static str, capacity: u32, period_secs: u64) -> Self {
+        RateLimitRule { name, key: RateLimitKey::UserId, capacity, period_secs }
+    }
+
+    pub const fn per_refresh_user(name: &'static str, capacity: u32, period_secs: u64) -> Self {
+        RateLimitRule { name, key: RateLimitKey::RefreshUserId, capacity, period_secs }
+    }
+
+    // Tokens added per millisecond.
+    pub fn refill_rate(&self) -> f64 {
+        (self.capacity as f64) / ((self.period_secs.max(1) * 1000) as f64)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // Seconds until the bucket is full again.
+    pub reset_secs: u64,
+    // Seconds until the next request would be allowed. Zero when allowed.
+    pub retry_after_secs: u64,
+}
+
+impl RateLimitDecision {
+    // `tokens` is what is left in the bucket after this request.
+    pub fn new(rule: &RateLimitRule, allowed: bool, tokens: f64) -> Self {
+        let rate = rule.refill_rate();
+        let secs_until = |target: f64| ((target - tokens).max(0.0) / rate / 1000.0).ceil() as u64;
+        RateLimitDecision {
+            allowed,
+            limit: rule.capacity,
+            remaining: tokens.floor() as u32,
+            reset_secs: secs_until(rule.capacity as f64),
+            retry_after_secs: if allowed { 0 } else { secs_until(1.0).max(1) },
+        }
+    }
+}
+
+// The state of one bucket. Backends store it however suits them.
+#[derive(Debug, Clone, Copy)]
+pub struct Bucket {
+    pub tokens: f64,
+    pub updated_at: i64,
+}
+
+impl Bucket {
+    // Refills the bucket up to `now` and takes a token if there is one.
+    pub fn take(
+        bucket: Option<Bucket>,
+        rule: &RateLimitRule,
+        now: i64
+    ) -> (Bucket, RateLimitDecision) {
+        let capacity = rule.capacity as f64;
+        let mut tokens = match bucket {
+            Some(bucket) => {
+                let elapsed = (now - bucket.updated_at).max(0) as f64;
+                (bucket.tokens + elapsed * rule.refill_rate()).min(capacity)
+            }
+            None => capacity,
+        };
+        let allowed = tokens >= 1.0;
+        if allowed {
+            tokens -= 1.0;
+        }
+        (Bucket { tokens, updated_at: now }, RateLimitDecision::new(rule, allowed, tokens))
+    }
+
+    // Milliseconds from `updated_at` until the bucket is full, after which it can be dropped.
+    pub fn full_after(&self, rule: &RateLimitRule) -> i64 {
+        ((rule.capacity as f64 - self.tokens).max(0.0) / rule.refill_rate()).ceil() as i64
+    }
+}
+
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    // Takes a token from the bucket at `key`, creating it full if it does not exist.
+    async fn take(
+        &self,
+        key: &str,
+        rule: &RateLimitRule,
+        now: DateTime
+    ) -> Result<RateLimitDecision, StoreError>;
+}
+
+pub struct RateLimiter {
+    enabled: bool,
+    key_prefix: String,
+    store: Box<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub async fn init(config: &RateLimitConfig) -> Result<RateLimiter, StoreError> {
+        let store = RateLimiter::store(config).await?;
+        Ok(RateLimiter { enabled: config.enabled, key_prefix: config.key_prefix.clone(), store })
+    }
+
+    #[cfg(feature = "redis")]
+    async fn store(config: &RateLimitConfig) -> Result<Box<dyn RateLimitStore>, StoreError> {
+        let store: Box<dyn RateLimitStore> = match &config.redis_url {
+            Some(url) => Box::new(self::redis::RedisRateLimitStore::init(url.expose()).await?),
+            None => Box::new(MemoryRateLimitStore::default()),
+        };
+        Ok(store)
+    }
+
+    // Config validation rejects RATE_LIMIT_REDIS_URL in builds without the `redis` feature.
+    #[cfg(not(feature = "redis"))]
+    async fn store(_config: &RateLimitConfig) -> Result<Box<dyn RateLimitStore>, StoreError> {
+        Ok(Box::new(MemoryRateLimitStore::default()))
+    }
+}
+
+// State for the `enforce` middleware: the rules of one route.
+#[derive(Clone)]
+pub struct RouteLimits {
+    app_state: Arc<AppState>,
+    rules: &'static [RateLimitRule],
+}
+
+impl RouteLimits {
+    pub fn new(app_state: Arc<AppState>, rules: &'static [RateLimitRule]) -> Self {
+        RouteLimits { app_state, rules }
+    }
+}
+
+// Applies every rule of the route whose key can be found in the request. The response carries
+// the `RateLimit-*` headers of the rule closest to its limit.
+pub async fn enforce(State(limits): State<RouteLimits>, request: Request, next: Next) -> Response {
+    let app_state = &limits.app_state;
+    let limiter = &app_state.rate_limiter;
+    if !limiter.enabled {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    // The body is only buffered when a rule needs the email from it.
+    let (body, email) = if limits.rules.iter().any(|rule| rule.key == RateLimitKey::Email) {
+        let bytes = match body::to_bytes(body, MAX_BODY_BYTES).await {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+            }
+        };
+        let email = serde_json
+            ::from_slice::<Value>(&bytes)
+            .ok()
+            .and_then(|value| value.get("email")?.as_str().map(|email| email.to_string()))
+            .map(|email| Email::normalize(&email, &app_state.config.email))
+            .filter(|email| !email.is_empty());
+        (Body::from(bytes), email)
+    } else {
+        (body, None)
+    };
+    let client = match ClientInfo::from_request_parts(&mut parts, app_state).await {
+        Ok(client) => client,
+        Err(err) => {
+            return err.into_response();
+        }
+    };
+    // Only the kind of token a rule asks for counts, so a refresh token never passes for an
+    // access token. Tokens without a type are old refresh tokens.
+    let claims = parts.headers
+        .get(AUTHORIZATION)
+        .and_then(|header| get_token(header).ok())
+        .and_then(|token| decode_jwt(&token, &app_state.config.session.jwt_secret).ok());
+    let (user_id, refresh_user_id) = match claims {
+        Some(claims) if claims.token_type == Some(TokenType::Access) => {
+            (Some(claims.user_id), None)
+        }
+        Some(claims) => (None, Some(claims.user_id)),
+        None => (None, None),
+    };
+
+    let mut tightest: Option<(&RateLimitRule, RateLimitDecision)> = None;
+    for rule in limits.rules {
+        let value = match rule.key {
+            RateLimitKey::Ip => Some(&client.ip),
+            RateLimitKey::Email => email.as_ref(),
+            RateLimitKey::UserId => user_id.as_ref(),
+            RateLimitKey::RefreshUserId => refresh_user_id.as_ref(),
+        };
+        let Some(value) = value else {
+            continue;
+        };
+        let key = format!("{}:{}:{}", limiter.key_prefix, rule.name, value);
+        // A broken limiter store should not take the whole API down with it.
+        let decision = match limiter.store.take(&key, rule, DateTime::now()).await {
+            Ok(decision) => decision,
+            Err(err) => {
+                log::warn!("Rate limiter unavailable, skipping rule {}: {}", rule.name, err);
+                continue;
+            }
+        };
+        if !decision.allowed {
+            let mut response = AppError::RateLimited(decision.retry_after_secs).into_response();
+            set_headers(response.headers_mut(), rule, &decision);
+            return response;
+        }
+        if tightest.is_none_or(|(_, current)| decision.remaining < current.remaining) {
+            tightest = Some((rule, decision));
+        }
+    }
+
+    let mut response = next.run(Request::from_parts(parts, body)).await;
+    if let Some((rule, decision)) = tightest {
+        set_headers(response.headers_mut(), rule, &decision);
+    }
+    response
+}
+
+fn set_headers(headers: &mut HeaderMap, rule: &RateLimitRule, decision: &RateLimitDecision) {
+    headers.insert("RateLimit-Limit", HeaderValue::from(decision.limit));
+    headers.insert("RateLimit-Remaining", HeaderValue::from(decision.remaining));
+    headers.insert("RateLimit-Reset", HeaderValue::from(decision.reset_secs));
+    let policy = format!("{};w={}", rule.capacity, rule.period_secs);
+    if let Ok(policy) = HeaderValue::from_str(&policy) {
+        headers.insert("RateLimit-Policy", policy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 4 requests per 4 seconds, so one token comes back every second.
+    const RULE: RateLimitRule = RateLimitRule::per_ip("test", 4, 4);
+
+    #[test]
+    fn a_new_bucket_starts_full() {
+        let (bucket, decision) = Bucket::take(None, &RULE, 0);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 3);
+        assert_eq!(bucket.tokens, 3.0);
+        assert_eq!(decision.reset_secs, 1);
+    }
+
+    #[test]
+    fn an_empty_bucket_denies_until_it_refills() {
+        let mut bucket = None;
+        for _ in 0..4 {
+            let (taken, decision) = Bucket::take(bucket, &RULE, 0);
+            assert!(decision.allowed);
+            bucket = Some(taken);
+        }
+        let (taken, decision) = Bucket::take(bucket, &RULE, 500);
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.retry_after_secs, 1);
+
+        let (_, decision) = Bucket::take(Some(taken), &RULE, 1000);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+    }
+
+    #[test]
+    fn refills_no_further_than_the_capacity() {
+        let (bucket, _) = Bucket::take(None, &RULE, 0);
+        let (bucket, decision) = Bucket::take(Some(bucket), &RULE, 60_000);
+        assert_eq!(decision.remaining, 3);
+        assert_eq!(bucket.full_after(&RULE), 1000);
+    }
+}
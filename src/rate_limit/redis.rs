@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use mongodb::bson::DateTime;
+use redis::{ aio::ConnectionManager, Script };
+
+use crate::database::error::StoreError;
+use super::{ RateLimitDecision, RateLimitRule, RateLimitStore };
+
+// Same refill as `Bucket::take`, run atomically on the server so instances can share buckets.
+// Tokens are returned as a string because Redis truncates Lua numbers to integers.
+const TAKE_SCRIPT: &str =
+    r"
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(state[1]) or capacity
+local updated_at = tonumber(state[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - updated_at) * rate)
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil((capacity - tokens) / rate) + 1000)
+return { allowed, tostring(tokens) }
+";
+
+// Works with Redis and anything speaking its protocol with Lua scripting, e.g. Valkey.
+pub struct RedisRateLimitStore {
+    connection: ConnectionManager,
+    script: Script,
+}
+
+impl RedisRateLimitStore {
+    pub async fn init(url: &str) -> Result<Self, StoreError> {
+        let client = redis::Client::open(url).map_err(redis_error)?;
+        let connection = ConnectionManager::new(client).await.map_err(redis_error)?;
+        Ok(RedisRateLimitStore { connection, script: Script::new(TAKE_SCRIPT) })
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    async fn take(
+        &self,
+        key: &str,
+        rule: &RateLimitRule,
+        now: DateTime
+    ) -> Result<RateLimitDecision, StoreError> {
+        let mut connection = self.connection.clone();
+        let (allowed, tokens): (i64, String) = self.script
+            .key(key)
+            .arg(rule.capacity)
+            .arg(rule.refill_rate())
+            .arg(now.timestamp_millis())
+            .invoke_async(&mut connection).await
+            .map_err(redis_error)?;
+        let tokens = tokens
+            .parse::<f64>()
+            .map_err(|err| StoreError::InvalidData(err.to_string()))?;
+        Ok(RateLimitDecision::new(rule, allowed == 1, tokens))
+    }
+}
+
+fn redis_error(err: redis::RedisError) -> StoreError {
+    StoreError::Unavailable(err.to_string())
+}
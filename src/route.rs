@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use axum::{ routing::post, Router };
+use axum::{ middleware, routing::{ delete, get, post, put }, Router };
 
 use crate::handlers::user::{
     login_google_user_handler,
@@ -9,16 +9,130 @@ use crate::handlers::user::{
     manual_login_user_handler,
     refresh_token_handler,
     account_verification_handler,
+    reset_password_handler,
 };
+use crate::handlers::admin::{
+    unlock_account_handler,
+    set_user_access_handler,
+    list_users_handler,
+    get_user_handler,
+    disable_user_handler,
+    enable_user_handler,
+    verify_user_handler,
+    force_password_reset_handler,
+    revoke_user_sessions_handler,
+    delete_user_handler,
+    list_audit_events_handler,
+};
+use crate::handlers::account::{
+    delete_account_handler,
+    cancel_account_deletion_handler,
+    export_account_data_handler,
+    security_events_handler,
+};
+use crate::handlers::session::{
+    list_sessions_handler,
+    revoke_session_handler,
+    revoke_all_sessions_handler,
+};
+use crate::models::user_model::ADMIN_ROLE;
+use crate::rate_limit::{ self, RateLimitRule, RouteLimits };
+use crate::utils::auth_user::{ self, Guard::{ self, RequireRole }, Guarded };
 use crate::AppState;
 
+// Registration sends an email, so it is limited per address as well as per client.
+const REGISTER_LIMITS: &[RateLimitRule] = &[
+    RateLimitRule::per_ip("register_ip", 10, 3600),
+    RateLimitRule::per_email("register_email", 3, 3600),
+];
+const LOGIN_LIMITS: &[RateLimitRule] = &[
+    RateLimitRule::per_ip("login_ip", 30, 60),
+    RateLimitRule::per_email("login_email", 10, 300),
+];
+const VERIFY_LIMITS: &[RateLimitRule] = &[
+    RateLimitRule::per_ip("verify_ip", 10, 60),
+    RateLimitRule::per_email("verify_email", 5, 600),
+];
+const RESET_PASSWORD_LIMITS: &[RateLimitRule] = &[
+    RateLimitRule::per_ip("reset_password_ip", 10, 60),
+    RateLimitRule::per_email("reset_password_email", 5, 600),
+];
+const GOOGLE_LOGIN_LIMITS: &[RateLimitRule] = &[RateLimitRule::per_ip("google_login_ip", 30, 60)];
+const LOGOUT_LIMITS: &[RateLimitRule] = &[RateLimitRule::per_ip("logout_ip", 30, 60)];
+const REFRESH_LIMITS: &[RateLimitRule] = &[
+    RateLimitRule::per_ip("refresh_ip", 60, 60),
+    RateLimitRule::per_refresh_user("refresh_user", 10, 60),
+];
+// Deleting checks the password, so it is limited like a login.
+const DELETE_ACCOUNT_LIMITS: &[RateLimitRule] = &[
+    RateLimitRule::per_ip("delete_account_ip", 10, 60),
+    RateLimitRule::per_user("delete_account_user", 5, 600),
+];
+const CANCEL_DELETION_LIMITS: &[RateLimitRule] = &[
+    RateLimitRule::per_ip("cancel_deletion_ip", 10, 60),
+    RateLimitRule::per_email("cancel_deletion_email", 5, 600),
+];
+const EXPORT_LIMITS: &[RateLimitRule] = &[RateLimitRule::per_user("export_user", 5, 3600)];
+const SECURITY_EVENTS_LIMITS: &[RateLimitRule] = &[
+    RateLimitRule::per_user("security_events_user", 30, 60),
+];
+const SESSION_LIMITS: &[RateLimitRule] = &[RateLimitRule::per_user("session_user", 30, 60)];
+const ADMIN_LIMITS: &[RateLimitRule] = &[RateLimitRule::per_ip("admin_ip", 60, 60)];
+
 pub fn create_router(app_state: Arc<AppState>) -> Router {
+    let limit = |rules: &'static [RateLimitRule]| {
+        middleware::from_fn_with_state(
+            RouteLimits::new(app_state.clone(), rules),
+            rate_limit::enforce
+        )
+    };
+    let guard = |guard: Guard| {
+        middleware::from_fn_with_state(Guarded::new(app_state.clone(), guard), auth_user::require)
+    };
+
+    // Rate limited before the guard, so bad tokens count against the limit too.
+    let admin = Router::new()
+        .route("/accounts/unlock", post(unlock_account_handler))
+        .route("/users", get(list_users_handler))
+        .route("/users/:id", get(get_user_handler).delete(delete_user_handler))
+        .route("/users/:id/access", put(set_user_access_handler))
+        .route("/users/:id/disable", post(disable_user_handler))
+        .route("/users/:id/enable", post(enable_user_handler))
+        .route("/users/:id/verify", post(verify_user_handler))
+        .route("/users/:id/password-reset", post(force_password_reset_handler))
+        .route("/users/:id/sessions", delete(revoke_user_sessions_handler))
+        .route("/audit-events", get(list_audit_events_handler))
+        .route_layer(guard(RequireRole(ADMIN_ROLE)))
+        .route_layer(limit(ADMIN_LIMITS));
+
     Router::new()
-        .route("/register", post(register_user_handler))
-        .route("/login", post(manual_login_user_handler))
-        .route("/account/verify", post(account_verification_handler))
-        .route("/login/google", post(login_google_user_handler))
-        .route("/logout", post(logout_user_handler))
-        .route("/refresh-token", post(refresh_token_handler))
+        .route("/register", post(register_user_handler).layer(limit(REGISTER_LIMITS)))
+        .route("/login", post(manual_login_user_handler).layer(limit(LOGIN_LIMITS)))
+        .route("/account/verify", post(account_verification_handler).layer(limit(VERIFY_LIMITS)))
+        .route(
+            "/password/reset",
+            post(reset_password_handler).layer(limit(RESET_PASSWORD_LIMITS))
+        )
+        .route("/login/google", post(login_google_user_handler).layer(limit(GOOGLE_LOGIN_LIMITS)))
+        .route("/logout", post(logout_user_handler).layer(limit(LOGOUT_LIMITS)))
+        .route("/refresh-token", post(refresh_token_handler).layer(limit(REFRESH_LIMITS)))
+        .route(
+            "/sessions",
+            get(list_sessions_handler)
+                .delete(revoke_all_sessions_handler)
+                .layer(limit(SESSION_LIMITS))
+        )
+        .route("/account", delete(delete_account_handler).layer(limit(DELETE_ACCOUNT_LIMITS)))
+        .route("/account/export", get(export_account_data_handler).layer(limit(EXPORT_LIMITS)))
+        .route(
+            "/account/security-events",
+            get(security_events_handler).layer(limit(SECURITY_EVENTS_LIMITS))
+        )
+        .route(
+            "/account/deletion/cancel",
+            post(cancel_account_deletion_handler).layer(limit(CANCEL_DELETION_LIMITS))
+        )
+        .route("/sessions/:id", delete(revoke_session_handler).layer(limit(SESSION_LIMITS)))
+        .nest("/admin", admin)
         .with_state(app_state)
 }
@@ -0,0 +1,299 @@
+use std::{ sync::Arc, time::Duration };
+
+use axum::{
+    extract::{ Json, State },
+    http::{ header::CONTENT_DISPOSITION, StatusCode },
+    response::{ IntoResponse, Response },
+};
+use mongodb::bson::{ oid::ObjectId, DateTime };
+use serde_json::{ json, Value };
+
+use crate::AppState;
+use crate::database::{ error::StoreError, store::AuditQuery };
+use crate::models::{
+    audit_event_model::{ AuditEventType, AuditEventView },
+    error_model::AppError,
+    login_attempt_model::LoginAttempt,
+    user_model::{
+        AccountStatus,
+        CodePurpose,
+        Email,
+        LoginTypes,
+        Password,
+        User,
+        UserView,
+    },
+};
+use crate::services::{
+    audit,
+    session::session_json,
+    user::{ send_email, store_code, success_response, verify_password },
+};
+use crate::utils::{
+    auth_user::AuthUser,
+    client_info::ClientInfo,
+    form_data::{ DeleteAccountForm, VerificationCodeForm },
+};
+
+// Kept as the status reason, so admins can tell it apart from a suspension.
+const DELETION_REASON: &str = "Deletion requested by the user";
+
+// Blocks the account and ends its sessions. The data is only purged after the grace period, so
+// the emailed code can still bring the account back until then.
+pub async fn request_account_deletion_service(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    Json(form): Json<DeleteAccountForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let user_id = auth_user.user_id;
+    let user = app_state.db.get_user_by_id(user_id).await?.ok_or(AppError::UserNotFound)?;
+    // Otherwise cancelling would lift a suspension.
+    user.check_status()?;
+    confirm_deletion(&app_state, &user, &form).await?;
+
+    let now = DateTime::now();
+    app_state.db
+        .update_user_status(
+            user_id,
+            AccountStatus::PENDING_DELETION,
+            Some(DELETION_REASON),
+            now
+        ).await?;
+    app_state.db.delete_user_sessions(user_id, None).await?;
+    let event = audit::action_event(
+        AuditEventType::DELETION_REQUESTED,
+        Some(user_id),
+        Some(user.email.as_str().clone()),
+        Some(user_id),
+        Some(&client)
+    );
+    audit::record_event(&app_state, event).await;
+
+    let purge_at = app_state.config.account_deletion.purge_at(now);
+    let email_sent = match send_cancel_code(&app_state, &user.email, purge_at).await {
+        Ok(()) => true,
+        Err(err) => {
+            log::warn!("Failed sending deletion notice to user {}: {}", user_id.to_hex(), err);
+            false
+        }
+    };
+    let data =
+        json!({
+            "deletion_scheduled_at": purge_at.try_to_rfc3339_string().ok(),
+            "email_sent": email_sent,
+        });
+    Ok(success_response("Account scheduled for deletion.", StatusCode::OK, data))
+}
+
+// Accounts with a password confirm with it, the others (e.g. Google) by repeating their email.
+async fn confirm_deletion(
+    app_state: &AppState,
+    user: &User,
+    form: &DeleteAccountForm
+) -> Result<(), AppError> {
+    match &user.password {
+        Some(hashed) => {
+            let password = Password::parse_login(form.password.clone().unwrap_or_default())?;
+            if !verify_password(app_state, &password, hashed).await? {
+                return Err(AppError::WrongPassword);
+            }
+        }
+        None => {
+            let email = form.email.clone().ok_or(AppError::EmailRequired)?;
+            let email = Email::parse(email, &app_state.config.email)?;
+            if email.as_str() != user.email.as_str() {
+                return Err(AppError::WrongEmail);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Longer than verification codes, since it is meant to be sent as part of a link.
+async fn send_cancel_code(
+    app_state: &AppState,
+    email: &Email,
+    purge_at: DateTime
+) -> Result<(), AppError> {
+    // Valid until the account is gone.
+    let code = store_code(app_state, email, 32, CodePurpose::DELETION_CANCEL, purge_at).await?;
+
+    let cancel = match &app_state.config.account_deletion.cancel_url {
+        Some(url) => {
+            let mut url = reqwest::Url
+                ::parse(url)
+                .map_err(|err| AppError::Internal(err.to_string()))?;
+            url.query_pairs_mut().append_pair("email", email.as_str()).append_pair("code", &code);
+            format!("To keep your account, open this link: {}", url)
+        }
+        None => format!("To keep your account, cancel the deletion with this code: {}", code),
+    };
+    let body = format!(
+        "Your account will be deleted on {}. {}\nIf you didn't ask for this, cancel the deletion.",
+        purge_at.try_to_rfc3339_string().unwrap_or_default(),
+        cancel
+    );
+    send_email(app_state, email, "Your account will be deleted", body).await
+}
+
+// Puts an account pending deletion back in the status it had before, with the emailed code. Its
+// sessions are gone, so the user logs in again afterwards.
+pub async fn cancel_account_deletion_service(
+    State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(form): Json<VerificationCodeForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let email = Email::parse(form.email.clone(), &app_state.config.email)?;
+    let purpose = CodePurpose::DELETION_CANCEL;
+    let code = app_state.db
+        .get_verification_code(email.as_str(), &form.code, purpose, DateTime::now()).await?;
+    if code.is_none() {
+        return Err(AppError::InvalidVerificationCode);
+    }
+    let user = app_state.db
+        .get_user_by_email(email.as_str().clone()).await?
+        .filter(|user| user.status == AccountStatus::PENDING_DELETION)
+        .ok_or(AppError::InvalidVerificationCode)?;
+    let user_id = user.id.ok_or_else(|| AppError::Internal("User ID not found.".to_string()))?;
+
+    let status = user.previous_status
+        .filter(|status| *status != AccountStatus::PENDING_DELETION)
+        .unwrap_or_default();
+    app_state.db.update_user_status(user_id, status, None, DateTime::now()).await?;
+    app_state.db.delete_verification_codes(email.as_str(), Some(purpose)).await?;
+    let event = audit::action_event(
+        AuditEventType::DELETION_CANCELLED,
+        Some(user_id),
+        Some(email.as_str().clone()),
+        None,
+        Some(&client)
+    );
+    audit::record_event(&app_state, event).await;
+    let message = match status {
+        AccountStatus::ACTIVE => "Account deletion cancelled. You can log in again.",
+        _ => "Account deletion cancelled.",
+    };
+    Ok(success_response(message, StatusCode::OK, ()))
+}
+
+// Everything stored about the user, for data access requests, as a file to download. Password
+// hashes, refresh tokens and codes are secrets, so only whether they exist is included.
+pub async fn export_account_data_service(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser
+) -> Result<Response, AppError> {
+    let user_id = auth_user.user_id;
+    let user = app_state.db.get_user_by_id(user_id).await?.ok_or(AppError::UserNotFound)?;
+    let email = user.email.as_str();
+    let timestamp = |time: Option<DateTime>| {
+        time.and_then(|time| time.try_to_rfc3339_string().ok())
+    };
+
+    let sessions: Vec<Value> = app_state.db
+        .list_sessions(user_id).await?
+        .iter()
+        .map(|session| session_json(session, &auth_user))
+        .collect();
+    let login_failures = app_state.db
+        .get_login_attempt(&LoginAttempt::account_key(email)).await?
+        .map(|attempt| {
+            json!({
+                "failures": attempt.failures,
+                "last_failure_at": timestamp(Some(attempt.last_failure_at)),
+                "locked_until": timestamp(attempt.locked_until),
+            })
+        });
+    let pending_codes = app_state.db.count_verification_codes(email, DateTime::now()).await?;
+    let query = AuditQuery { user_id: Some(user_id), ..AuditQuery::default() };
+    // All of them. Mongo takes the limit as an i64.
+    let (events, _) = app_state.db.search_audit_events(&query, 0, i64::MAX as u64).await?;
+    let security_events: Vec<AuditEventView> = events.iter().map(AuditEventView::from).collect();
+    // What the user did to other accounts, such as admin actions. Actions on their own account
+    // are already in `security_events`.
+    let query = AuditQuery { actor_id: Some(user_id), ..AuditQuery::default() };
+    let (events, _) = app_state.db.search_audit_events(&query, 0, i64::MAX as u64).await?;
+    let actions_performed: Vec<AuditEventView> = events
+        .iter()
+        .filter(|event| event.user_id != Some(user_id))
+        .map(AuditEventView::from)
+        .collect();
+
+    let archive =
+        json!({
+            "exported_at": timestamp(Some(DateTime::now())),
+            "profile": UserView::from(&user),
+            "has_password": user.password.is_some(),
+            "linked_identities": linked_identities(&user),
+            "sessions": sessions,
+            "login_failures": login_failures,
+            "pending_verification_codes": pending_codes,
+            "security_events": security_events,
+            "actions_performed": actions_performed,
+            // Nothing records consent yet, so this stays empty until something does.
+            "consents": [],
+        });
+    let disposition = format!("attachment; filename=\"account-{}.json\"", user_id.to_hex());
+    Ok((StatusCode::OK, [(CONTENT_DISPOSITION, disposition)], Json(archive)).into_response())
+}
+
+// Accounts created through a provider are linked to the identity it vouched for.
+fn linked_identities(user: &User) -> Vec<Value> {
+    match user.login_type {
+        LoginTypes::MANUAL => vec![],
+        ref provider => {
+            vec![json!({ "provider": provider.as_str(), "email": user.email.as_str() })]
+        }
+    }
+}
+
+// Removes the user along with their sessions, pending codes and login failures. Returns whether
+// the user still existed. Audit events stay as the security record of the account, but with the
+// email replaced by `audit::pseudonym` and the user's own IPs and user agents cleared.
+pub async fn erase_user(
+    app_state: &AppState,
+    user_id: ObjectId,
+    email: &Email
+) -> Result<bool, StoreError> {
+    let email = email.as_str();
+    app_state.db.delete_user_sessions(user_id, None).await?;
+    app_state.db.delete_verification_codes(email, None).await?;
+    app_state.db.clear_login_attempts(&LoginAttempt::account_key(email)).await?;
+    let pseudonym = audit::pseudonym(email);
+    app_state.db.pseudonymize_audit_events(user_id, email, &pseudonym).await?;
+    Ok(app_state.db.delete_user(user_id).await? > 0)
+}
+
+// Erases accounts whose grace period is over, every `purge_interval_secs`.
+pub async fn purge_deleted_accounts(app_state: Arc<AppState>) {
+    let config = &app_state.config.account_deletion;
+    let period = Duration::from_secs(config.purge_interval_secs.max(1));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let now = DateTime::now().timestamp_millis();
+        let requested_before = DateTime::from_millis(now - config.grace_period_secs * 1000);
+        let users = match app_state.db.get_users_pending_deletion(requested_before).await {
+            Ok(users) => users,
+            Err(err) => {
+                log::warn!("Failed looking up accounts to purge: {}", err);
+                continue;
+            }
+        };
+        for user in users {
+            let Some(user_id) = user.id else {
+                continue;
+            };
+            match erase_user(&app_state, user_id, &user.email).await {
+                Ok(_) => {
+                    log::info!("Purged deleted account {}", user_id.to_hex());
+                    let email = Some(audit::pseudonym(user.email.as_str()));
+                    let event_type = AuditEventType::ACCOUNT_DELETED;
+                    let event = audit::action_event(event_type, Some(user_id), email, None, None);
+                    audit::record_event(&app_state, event).await;
+                }
+                Err(err) => log::warn!("Failed purging account {}: {}", user_id.to_hex(), err),
+            }
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::sync::Arc;
+
+use axum::{ extract::{ Json, State }, http::StatusCode };
+use mongodb::bson::{ oid::ObjectId, DateTime };
+use serde_json::{ json, Value };
+
+use crate::AppState;
+use crate::database::store::UserQuery;
+use crate::models::{
+    audit_event_model::{ AuditEvent, AuditEventType },
+    error_model::AppError,
+    response_model::ResponseBuilder,
+    user_model::{ AccountStatus, CodePurpose, LoginTypes, User, UserView, ADMIN_ROLE },
+};
+use crate::services::{
+    account::erase_user,
+    audit,
+    user::{ send_password_reset_code, success_response },
+};
+use crate::utils::{
+    auth_user::AuthUser,
+    client_info::ClientInfo,
+    form_data::{ UserAccessForm, UserListQuery },
+    obj_id_converter::Converter,
+};
+
+pub const DEFAULT_PAGE_SIZE: u64 = 20;
+pub const MAX_PAGE_SIZE: u64 = 100;
+
+// Names end up in tokens and comma separated SQL columns, so they are kept to a safe alphabet,
+// e.g. "admin" or "users:read".
+fn is_valid_grant_name(name: &str) -> bool {
+    let allowed = |c: char| {
+        c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-' | ':' | '.')
+    };
+    !name.is_empty() && name.len() <= 64 && name.chars().all(allowed)
+}
+
+// Recorded against the user acted on, with the admin as the actor.
+async fn record_admin_action(
+    app_state: &AppState,
+    event_type: AuditEventType,
+    user: &User,
+    admin: &AuthUser,
+    client: &ClientInfo,
+    reason: Option<String>
+) {
+    let email = Some(user.email.as_str().clone());
+    let event = audit::action_event(event_type, user.id, email, Some(admin.user_id), Some(client));
+    audit::record_event(app_state, AuditEvent { reason, ..event }).await;
+}
+
+async fn find_user(app_state: &AppState, user_id: String) -> Result<(ObjectId, User), AppError> {
+    let user_id = Converter::string_to_bson(user_id)?;
+    let user = app_state.db.get_user_by_id(user_id).await?.ok_or(AppError::UserNotFound)?;
+    Ok((user_id, user))
+}
+
+// Admins can't lock themselves out, and the last active admin can't be suspended, deleted or
+// lose the role. Two admins demoting each other at the same time can still both succeed.
+async fn check_admin_change(
+    app_state: &AppState,
+    admin: &AuthUser,
+    user: &User,
+    keeps_admin: bool
+) -> Result<(), AppError> {
+    if user.id == Some(admin.user_id) {
+        return Err(AppError::OwnAccount);
+    }
+    let is_active_admin = |user: &User| {
+        user.status == AccountStatus::ACTIVE && user.grants().has_role(ADMIN_ROLE)
+    };
+    if keeps_admin || !is_active_admin(user) {
+        return Ok(());
+    }
+    let admins = app_state.db.list_users_with_role(ADMIN_ROLE).await?;
+    let others = admins.iter().filter(|admin| admin.id != user.id && is_active_admin(admin));
+    if others.count() == 0 {
+        return Err(AppError::LastAdmin);
+    }
+    Ok(())
+}
+
+// How many results come before `page`, for pages counted from 1. Databases take offsets as
+// signed 64-bit numbers.
+pub fn page_offset(page: u64, per_page: u64) -> Result<u64, AppError> {
+    (page - 1)
+        .checked_mul(per_page)
+        .filter(|offset| *offset <= i64::MAX as u64)
+        .ok_or_else(|| AppError::InvalidQuery("page is out of range".to_string()))
+}
+
+pub fn parse_date(key: &str, value: &Option<String>) -> Result<Option<DateTime>, AppError> {
+    value
+        .as_ref()
+        .map(|value| {
+            DateTime::parse_rfc3339_str(value).map_err(|_| {
+                AppError::InvalidQuery(format!("{} must be an RFC 3339 date", key))
+            })
+        })
+        .transpose()
+}
+
+// Tokens already issued keep the old grants until they expire; the next refresh picks up the
+// new ones.
+pub async fn set_user_access_service(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    user_id: String,
+    Json(form): Json<UserAccessForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let mut names = form.roles.iter().chain(form.permissions.iter());
+    if let Some(name) = names.find(|name| !is_valid_grant_name(name)) {
+        return Err(AppError::InvalidGrantName(name.clone()));
+    }
+    let mut roles = form.roles.clone();
+    let mut permissions = form.permissions.clone();
+    for names in [&mut roles, &mut permissions] {
+        names.sort();
+        names.dedup();
+    }
+
+    let (user_id, user) = find_user(&app_state, user_id).await?;
+    let keeps_admin = roles.iter().any(|role| role == ADMIN_ROLE);
+    check_admin_change(&app_state, &admin, &user, keeps_admin).await?;
+    app_state.db.set_user_access(user_id, &roles, &permissions).await?;
+    let event_type = AuditEventType::ACCESS_CHANGED;
+    record_admin_action(&app_state, event_type, &user, &admin, &client, None).await;
+    let data = json!({ "roles": roles, "permissions": permissions });
+    Ok(success_response("User access updated.", StatusCode::OK, data))
+}
+
+pub async fn list_users_service(
+    State(app_state): State<Arc<AppState>>,
+    params: UserListQuery
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let login_type = params.login_type
+        .as_ref()
+        .map(|login_type| {
+            LoginTypes::from_stored(&login_type.to_uppercase()).ok_or_else(|| {
+                AppError::InvalidQuery(format!("unknown login_type {}", login_type))
+            })
+        })
+        .transpose()?;
+    let query = UserQuery {
+        search: params.search.clone().filter(|search| !search.trim().is_empty()),
+        login_type,
+        is_verified: params.is_verified,
+        created_after: parse_date("created_after", &params.created_after)?,
+        created_before: parse_date("created_before", &params.created_before)?,
+    };
+    let page = params.page.unwrap_or(1).max(1);
+    let per_page = params.per_page.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let offset = page_offset(page, per_page)?;
+    let (users, total) = app_state.db.search_users(&query, offset, per_page).await?;
+    let users: Vec<UserView> = users.iter().map(UserView::from).collect();
+    let meta = json!({
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+        "total_pages": total.div_ceil(per_page),
+    });
+    let response = ResponseBuilder::new(StatusCode::OK)
+        .message("Users retrieved.")
+        .data(users)
+        .meta(meta)
+        .build();
+    Ok(response)
+}
+
+pub async fn get_user_service(
+    State(app_state): State<Arc<AppState>>,
+    user_id: String
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let (user_id, user) = find_user(&app_state, user_id).await?;
+    let now = DateTime::now();
+    let active_sessions = app_state.db
+        .list_sessions(user_id).await?
+        .iter()
+        .filter(|session| session.expires_at.is_none_or(|expires_at| expires_at > now))
+        .count();
+    let data = json!({ "user": UserView::from(&user), "active_sessions": active_sessions });
+    Ok(success_response("User retrieved.", StatusCode::OK, data))
+}
+
+// Blocking also ends every session, so the user is out right away rather than when their
+// access token expires.
+pub async fn set_user_status_service(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    user_id: String,
+    status: AccountStatus,
+    reason: Option<String>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let (user_id, user) = find_user(&app_state, user_id).await?;
+    check_admin_change(&app_state, &admin, &user, status == AccountStatus::ACTIVE).await?;
+    let reason = reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+    app_state.db.update_user_status(user_id, status, reason.as_deref(), DateTime::now()).await?;
+    if status != AccountStatus::ACTIVE {
+        app_state.db.delete_user_sessions(user_id, None).await?;
+    }
+    let event_type = match status {
+        AccountStatus::ACTIVE => AuditEventType::ACCOUNT_ENABLED,
+        AccountStatus::SUSPENDED => AuditEventType::ACCOUNT_DISABLED,
+        AccountStatus::PENDING_DELETION => AuditEventType::DELETION_REQUESTED,
+    };
+    record_admin_action(&app_state, event_type, &user, &admin, &client, reason).await;
+    let message = match status {
+        AccountStatus::ACTIVE => "User enabled.",
+        AccountStatus::SUSPENDED => "User disabled.",
+        AccountStatus::PENDING_DELETION => "User scheduled for deletion.",
+    };
+    Ok(success_response(message, StatusCode::OK, ()))
+}
+
+pub async fn verify_user_service(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    user_id: String
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let (_, user) = find_user(&app_state, user_id).await?;
+    app_state.db.update_user_verification(user.email.as_str()).await?;
+    let purpose = Some(CodePurpose::ACCOUNT_VERIFICATION);
+    app_state.db.delete_verification_codes(user.email.as_str(), purpose).await?;
+    let event_type = AuditEventType::VERIFY_ACCOUNT;
+    record_admin_action(&app_state, event_type, &user, &admin, &client, None).await;
+    Ok(success_response("User verified.", StatusCode::OK, ()))
+}
+
+// The user can't log in with their password until they set a new one with the emailed code.
+pub async fn force_password_reset_service(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    user_id: String
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let (user_id, user) = find_user(&app_state, user_id).await?;
+    app_state.db.set_password_reset_required(user_id, true).await?;
+    app_state.db.delete_user_sessions(user_id, None).await?;
+    let event_type = AuditEventType::PASSWORD_RESET_REQUIRED;
+    record_admin_action(&app_state, event_type, &user, &admin, &client, None).await;
+    // The flag is set either way; the user gets a new code on their next login attempt.
+    let email_sent = match send_password_reset_code(&app_state, &user.email).await {
+        Ok(()) => true,
+        Err(err) => {
+            log::warn!("Failed sending reset code to user {}: {}", user_id.to_hex(), err);
+            false
+        }
+    };
+    let data = json!({ "email_sent": email_sent });
+    Ok(success_response("Password reset required.", StatusCode::OK, data))
+}
+
+pub async fn revoke_user_sessions_service(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    user_id: String
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let (user_id, user) = find_user(&app_state, user_id).await?;
+    let revoked = app_state.db.delete_user_sessions(user_id, None).await?;
+    let event_type = AuditEventType::SESSION_REVOKED;
+    record_admin_action(&app_state, event_type, &user, &admin, &client, None).await;
+    Ok(success_response("Sessions revoked.", StatusCode::OK, json!({ "revoked": revoked })))
+}
+
+// Removes the user right away, without the grace period of self-service deletion.
+pub async fn delete_user_service(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    user_id: String
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let (user_id, user) = find_user(&app_state, user_id).await?;
+    check_admin_change(&app_state, &admin, &user, false).await?;
+    if !erase_user(&app_state, user_id, &user.email).await? {
+        return Err(AppError::UserNotFound);
+    }
+    // Like the events before it, this one only keeps a pseudonym of the email.
+    let email = Some(audit::pseudonym(user.email.as_str()));
+    let event_type = AuditEventType::ACCOUNT_DELETED;
+    let actor_id = Some(admin.user_id);
+    let event = audit::action_event(event_type, Some(user_id), email, actor_id, Some(&client));
+    audit::record_event(&app_state, event).await;
+    Ok(success_response("User deleted.", StatusCode::OK, ()))
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use axum::{ extract::{ Json, State }, http::StatusCode };
+use mongodb::bson::{ oid::ObjectId, DateTime };
+use serde_json::{ json, Value };
+use sha2::{ Digest, Sha256 };
+
+use crate::AppState;
+use crate::database::store::AuditQuery;
+use crate::models::{
+    audit_event_model::{ AuditEvent, AuditEventType, AuditEventView, AuditOutcome },
+    error_model::AppError,
+    response_model::ResponseBuilder,
+    user_model::Email,
+};
+use crate::services::admin::{ page_offset, parse_date, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE };
+use crate::utils::{
+    auth_user::AuthUser,
+    client_info::ClientInfo,
+    form_data::AuditEventListQuery,
+    obj_id_converter::Converter,
+};
+
+// Emails are kept as submitted, so junk input is cut short before it is stored.
+const MAX_EMAIL_LENGTH: usize = 320;
+
+// A lost event is logged, but never fails the request it was about.
+pub async fn record_event(app_state: &AppState, event: AuditEvent) {
+    if let Err(err) = app_state.db.record_audit_event(&event).await {
+        log::warn!("Failed recording {} audit event: {}", event.event_type.as_str(), err);
+    }
+}
+
+// Stands in for the email of an erased account in its audit events. The same email always gives
+// the same pseudonym, so an operator who knows the address can still find its events.
+pub fn pseudonym(email: &str) -> String {
+    format!("erased:{:x}", Sha256::digest(email.as_bytes()))
+}
+
+// Records how a request made with `email` turned out. The event is linked to the account the
+// email belongs to, so failed attempts show up in that user's security history too.
+pub async fn record_attempt<T>(
+    app_state: &AppState,
+    event_type: AuditEventType,
+    client: &ClientInfo,
+    email: &str,
+    result: &Result<T, AppError>
+) {
+    let email: String = Email::normalize(email, &app_state.config.email)
+        .chars()
+        .take(MAX_EMAIL_LENGTH)
+        .collect();
+    let user_id = match app_state.db.get_user_by_email(email.clone()).await {
+        Ok(user) => user.and_then(|user| user.id),
+        Err(err) => {
+            log::warn!("Failed looking up the user of a {} event: {}", event_type.as_str(), err);
+            None
+        }
+    };
+    let (outcome, reason) = match result {
+        Ok(_) => (AuditOutcome::SUCCESS, None),
+        Err(err) => (AuditOutcome::FAILURE, Some(err.code().to_string())),
+    };
+    let event = AuditEvent {
+        id: None,
+        event_type,
+        outcome,
+        reason,
+        user_id,
+        actor_id: None,
+        email: Some(email),
+        ip: Some(client.ip.clone()),
+        user_agent: client.user_agent.clone(),
+        created_at: DateTime::now(),
+    };
+    record_event(app_state, event).await;
+}
+
+// A successful action on the account `user_id`, by `actor_id`. `client` is None when the
+// service acts on its own.
+pub fn action_event(
+    event_type: AuditEventType,
+    user_id: Option<ObjectId>,
+    email: Option<String>,
+    actor_id: Option<ObjectId>,
+    client: Option<&ClientInfo>
+) -> AuditEvent {
+    AuditEvent {
+        id: None,
+        event_type,
+        outcome: AuditOutcome::SUCCESS,
+        reason: None,
+        user_id,
+        actor_id,
+        email,
+        ip: client.map(|client| client.ip.clone()),
+        user_agent: client.and_then(|client| client.user_agent.clone()),
+        created_at: DateTime::now(),
+    }
+}
+
+fn audit_query(
+    app_state: &AppState,
+    params: &AuditEventListQuery
+) -> Result<AuditQuery, AppError> {
+    let event_type = params.event_type
+        .as_ref()
+        .map(|event_type| {
+            AuditEventType::from_stored(&event_type.to_uppercase()).ok_or_else(|| {
+                AppError::InvalidQuery(format!("unknown event_type {}", event_type))
+            })
+        })
+        .transpose()?;
+    let outcome = params.outcome
+        .as_ref()
+        .map(|outcome| {
+            AuditOutcome::from_stored(&outcome.to_uppercase()).ok_or_else(|| {
+                AppError::InvalidQuery(format!("unknown outcome {}", outcome))
+            })
+        })
+        .transpose()?;
+    Ok(AuditQuery {
+        user_id: params.user_id.clone().map(Converter::string_to_bson).transpose()?,
+        actor_id: params.actor_id.clone().map(Converter::string_to_bson).transpose()?,
+        email: params.email
+            .as_ref()
+            .filter(|email| !email.trim().is_empty())
+            .map(|email| Email::normalize(email, &app_state.config.email)),
+        event_type,
+        outcome,
+        ip: params.ip.clone().filter(|ip| !ip.trim().is_empty()),
+        created_after: parse_date("created_after", &params.created_after)?,
+        created_before: parse_date("created_before", &params.created_before)?,
+    })
+}
+
+async fn audit_events_page(
+    app_state: &AppState,
+    query: &AuditQuery,
+    params: &AuditEventListQuery
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let page = params.page.unwrap_or(1).max(1);
+    let per_page = params.per_page.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let offset = page_offset(page, per_page)?;
+    let (events, total) = app_state.db.search_audit_events(query, offset, per_page).await?;
+    let events: Vec<AuditEventView> = events.iter().map(AuditEventView::from).collect();
+    let meta = json!({
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+        "total_pages": total.div_ceil(per_page),
+    });
+    let response = ResponseBuilder::new(StatusCode::OK)
+        .message("Audit events retrieved.")
+        .data(events)
+        .meta(meta)
+        .build();
+    Ok(response)
+}
+
+// Every recorded event, for admins.
+pub async fn list_audit_events_service(
+    State(app_state): State<Arc<AppState>>,
+    params: AuditEventListQuery
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let query = audit_query(&app_state, &params)?;
+    audit_events_page(&app_state, &query, &params).await
+}
+
+// The security history of the signed in user.
+pub async fn list_security_events_service(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    params: AuditEventListQuery
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let query = audit_query(&app_state, &params)?;
+    let query = AuditQuery { user_id: Some(auth_user.user_id), ..query };
+    audit_events_page(&app_state, &query, &params).await
+}
@@ -0,0 +1,199 @@
+use std::{ sync::Arc, time::Duration };
+
+use axum::{ extract::{ Json, State }, http::StatusCode };
+use mongodb::bson::DateTime;
+use serde_json::Value;
+
+use crate::AppState;
+use crate::config::config::LoginProtectionConfig;
+use crate::models::{
+    audit_event_model::AuditEventType,
+    error_model::AppError,
+    login_attempt_model::LoginAttempt,
+    user_model::Email,
+};
+use crate::services::{ audit, user::success_response };
+use crate::utils::{
+    auth_user::AuthUser,
+    client_info::ClientInfo,
+    form_data::UnlockAccountForm,
+};
+
+// Rejects the attempt while the account or the client IP is locked out.
+pub async fn check_login_allowed(
+    app_state: &AppState,
+    email: &Email,
+    client: &ClientInfo
+) -> Result<(), AppError> {
+    let now = DateTime::now();
+    let account_key = LoginAttempt::account_key(email.as_str());
+    if let Some(until) = locked_until(app_state, &account_key, now).await? {
+        return Err(AppError::AccountLocked(until));
+    }
+    let ip_key = LoginAttempt::ip_key(&client.ip);
+    if let Some(until) = locked_until(app_state, &ip_key, now).await? {
+        return Err(AppError::TooManyAttempts(until));
+    }
+    Ok(())
+}
+
+async fn locked_until(
+    app_state: &AppState,
+    key: &str,
+    now: DateTime
+) -> Result<Option<DateTime>, AppError> {
+    let attempt = app_state.db.get_login_attempt(key).await?;
+    Ok(attempt.and_then(|attempt| attempt.locked_until).filter(|until| *until > now))
+}
+
+// Counts a failed attempt against both the account and the IP, locking either one when it
+// crosses its limits.
+pub async fn record_login_failure(
+    app_state: &AppState,
+    email: &Email,
+    client: &ClientInfo
+) -> Result<(), AppError> {
+    let config = &app_state.config.login_protection;
+    let limits = [
+        (
+            LoginAttempt::account_key(email.as_str()),
+            config.account_free_attempts,
+            config.account_lockout_threshold,
+        ),
+        (LoginAttempt::ip_key(&client.ip), config.ip_free_attempts, config.ip_lockout_threshold),
+    ];
+
+    for (key, free_attempts, lockout_threshold) in limits {
+        let now = DateTime::now();
+        let window_start = now.saturating_add_millis(-config.failure_window_secs * 1000);
+        if let Some(attempt) = app_state.db.get_login_attempt(&key).await? {
+            if attempt.last_failure_at < window_start {
+                app_state.db.clear_login_attempts(&key).await?;
+            }
+        }
+
+        let failures = app_state.db.increment_login_failures(&key, now).await?;
+        if let Some(secs) = lock_secs(config, failures, free_attempts, lockout_threshold) {
+            app_state.db.lock_login(&key, now.saturating_add_millis(secs * 1000)).await?;
+        }
+    }
+    Ok(())
+}
+
+// Deletes counters past the failure window and any lock every `purge_interval_secs`. Mongo does
+// it on its own with a TTL index.
+pub async fn purge_stale_login_attempts(app_state: Arc<AppState>) {
+    let config = &app_state.config.login_protection;
+    let period = Duration::from_secs(config.purge_interval_secs.max(1));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let now = DateTime::now();
+        let before = now.saturating_add_millis(-config.retention_secs() * 1000);
+        match app_state.db.delete_stale_login_attempts(before, now).await {
+            Ok(0) => {}
+            Ok(purged) => log::info!("Purged {} stale login attempt counters", purged),
+            Err(err) => log::warn!("Failed purging stale login attempt counters: {}", err),
+        }
+    }
+}
+
+fn lock_secs(
+    config: &LoginProtectionConfig,
+    failures: i64,
+    free_attempts: i64,
+    lockout_threshold: i64
+) -> Option<i64> {
+    if failures >= lockout_threshold {
+        return Some(config.lockout_secs);
+    }
+    if failures <= free_attempts {
+        return None;
+    }
+    let doublings = (failures - free_attempts - 1).min(32) as u32;
+    Some(config.base_delay_secs.saturating_mul(1 << doublings).min(config.max_delay_secs))
+}
+
+// A successful login wipes the account's failures. The IP counter is left to expire on its own
+// since other users may share the address.
+pub async fn clear_login_failures(app_state: &AppState, email: &Email) -> Result<(), AppError> {
+    app_state.db.clear_login_attempts(&LoginAttempt::account_key(email.as_str())).await?;
+    Ok(())
+}
+
+pub async fn unlock_account_service(
+    State(app_state): State<Arc<AppState>>,
+    admin: AuthUser,
+    client: ClientInfo,
+    Json(form): Json<UnlockAccountForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let email = Email::parse(form.email.clone(), &app_state.config.email)?;
+    app_state.db.clear_login_attempts(&LoginAttempt::account_key(email.as_str())).await?;
+    if let Some(ip) = &form.ip {
+        app_state.db.clear_login_attempts(&LoginAttempt::ip_key(ip)).await?;
+    }
+    // The email may not belong to an account.
+    let user = app_state.db.get_user_by_email(email.as_str().clone()).await?;
+    let user_id = user.and_then(|user| user.id);
+    let event = audit::action_event(
+        AuditEventType::ACCOUNT_UNLOCKED,
+        user_id,
+        Some(email.as_str().clone()),
+        Some(admin.user_id),
+        Some(&client)
+    );
+    audit::record_event(&app_state, event).await;
+    Ok(success_response("Account unlocked.", StatusCode::OK, ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::source::ConfigSource;
+    use crate::test_support::{ app_state, login, verified_user, PASSWORD };
+    use super::*;
+
+    const SETTINGS: [(&str, &str); 2] = [
+        ("LOGIN_ACCOUNT_FREE_ATTEMPTS", "3"),
+        ("LOGIN_ACCOUNT_LOCKOUT_THRESHOLD", "3"),
+    ];
+
+    #[tokio::test]
+    async fn repeated_failures_lock_the_account() {
+        let app_state = app_state(&SETTINGS).await;
+        verified_user(&app_state, "ann@example.com").await;
+        for _ in 0..3 {
+            let result = login(&app_state, "ann@example.com", "Wr0ng!pass").await;
+            assert!(matches!(result, Err(AppError::WrongPassword)));
+        }
+        let result = login(&app_state, "ann@example.com", PASSWORD).await;
+        assert!(matches!(result, Err(AppError::AccountLocked(_))));
+    }
+
+    #[tokio::test]
+    async fn a_successful_login_clears_the_failures() {
+        let app_state = app_state(&SETTINGS).await;
+        verified_user(&app_state, "ann@example.com").await;
+        for _ in 0..2 {
+            login(&app_state, "ann@example.com", "Wr0ng!pass").await.unwrap_err();
+        }
+        let (status, _) = login(&app_state, "ann@example.com", PASSWORD).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        for _ in 0..2 {
+            login(&app_state, "ann@example.com", "Wr0ng!pass").await.unwrap_err();
+        }
+        let (status, _) = login(&app_state, "ann@example.com", PASSWORD).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[test]
+    fn delays_double_up_to_the_maximum() {
+        let source = ConfigSource::from_values(&[("LOGIN_MAX_DELAY_SECS", "5")]);
+        let config = LoginProtectionConfig::init(&source);
+        let delays: Vec<Option<i64>> = (1..=7)
+            .map(|failures| lock_secs(&config, failures, 3, 7))
+            .collect();
+        assert_eq!(delays, [None, None, None, Some(1), Some(2), Some(4), Some(900)]);
+        assert_eq!(lock_secs(&config, 6, 3, 10), Some(4));
+        assert_eq!(lock_secs(&config, 7, 3, 10), Some(5));
+    }
+}
@@ -1 +1,6 @@
 pub mod user;
+pub mod login_protection;
+pub mod session;
+pub mod admin;
+pub mod account;
+pub mod audit;
@@ -0,0 +1,272 @@
+use std::{ sync::Arc, time::Duration };
+
+use axum::{ extract::{ Json, State }, http::StatusCode };
+use mongodb::bson::DateTime;
+use serde_json::{ json, Value };
+
+use crate::AppState;
+use crate::models::{
+    audit_event_model::AuditEventType,
+    error_model::AppError,
+    refresh_token_model::RefreshToken,
+};
+use crate::services::{ audit, user::success_response };
+use crate::utils::{
+    auth_user::AuthUser,
+    client_info::ClientInfo,
+    jwt::{ decode_jwt, sign_jwt, Grants, TokenType },
+    obj_id_converter::Converter,
+};
+
+// Trades a refresh token for a new pair. The old refresh token stops working, so a revoked
+// session can't be refreshed and a stolen token is only good until its owner refreshes.
+// Each refresh extends the session by the idle timeout, but never past its absolute lifetime.
+pub async fn refresh_token_service(
+    State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
+    refresh_token: String
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let session_config = &app_state.config.session;
+    let claims = decode_jwt(&refresh_token, &session_config.jwt_secret)?;
+    if claims.token_type == Some(TokenType::Access) {
+        return Err(AppError::InvalidToken);
+    }
+    let session = app_state.db
+        .get_session_by_refresh_token(&refresh_token).await?
+        .ok_or(AppError::InvalidToken)?;
+    let user_id = match session.user_id {
+        Some(user_id) if user_id.to_hex() == claims.user_id => user_id,
+        _ => {
+            return Err(AppError::InvalidToken);
+        }
+    };
+
+    let now = DateTime::now();
+    if is_expired(&session, now) {
+        return Err(AppError::ExpiredToken);
+    }
+    // Sessions from before expiry was tracked count as started now, which the rotation stores.
+    let started_at = session.session_started_at.unwrap_or(now);
+    let expires_at = session_config.expires_at(started_at, now);
+    let refresh_lifetime_secs = (expires_at.timestamp_millis() - now.timestamp_millis()) / 1000;
+    if refresh_lifetime_secs <= 0 {
+        return Err(AppError::ExpiredToken);
+    }
+
+    // Read again so role changes apply from the next refresh on.
+    let user = app_state.db.get_user_by_id(user_id).await?.ok_or(AppError::InvalidToken)?;
+    user.check_status()?;
+
+    let session_id = session.id.map(|id| id.to_hex());
+    let new_refresh_token = sign_jwt(
+        TokenType::Refresh,
+        &claims.user_id,
+        session_id.as_deref(),
+        &Grants::default(),
+        refresh_lifetime_secs,
+        &session_config.jwt_secret
+    )?;
+    app_state.db
+        .rotate_refresh_token(
+            &refresh_token,
+            &new_refresh_token,
+            started_at,
+            now,
+            expires_at,
+            &client.ip,
+            client.user_agent.as_deref()
+        ).await?
+        // Someone else rotated or revoked it since we looked it up.
+        .ok_or(AppError::InvalidToken)?;
+    let new_access_token = sign_jwt(
+        TokenType::Access,
+        &claims.user_id,
+        session_id.as_deref(),
+        &user.grants(),
+        session_config.access_token_lifetime_secs,
+        &session_config.jwt_secret
+    )?;
+    let event = audit::action_event(
+        AuditEventType::TOKEN_REFRESH,
+        Some(user_id),
+        Some(user.email.as_str().clone()),
+        Some(user_id),
+        Some(&client)
+    );
+    audit::record_event(&app_state, event).await;
+    let data =
+        json!({
+            "new_refresh_token": new_refresh_token,
+            "new_access_token": new_access_token
+        });
+    Ok((StatusCode::OK, Json(data)))
+}
+
+fn is_expired(session: &RefreshToken, now: DateTime) -> bool {
+    session.expires_at.is_some_and(|expires_at| expires_at <= now)
+}
+
+// Removes expired sessions every `purge_interval_secs`. Mongo's TTL index does this on its own,
+// the other backends rely on this task.
+pub async fn purge_expired_sessions(app_state: Arc<AppState>) {
+    let period = Duration::from_secs(app_state.config.session.purge_interval_secs.max(1));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        match app_state.db.delete_expired_sessions(DateTime::now()).await {
+            Ok(0) => {}
+            Ok(purged) => log::info!("Purged {} expired sessions", purged),
+            Err(err) => log::warn!("Failed purging expired sessions: {}", err),
+        }
+    }
+}
+
+pub fn session_json(session: &RefreshToken, auth_user: &AuthUser) -> Value {
+    let timestamp = |time: Option<DateTime>| {
+        time.and_then(|time| time.try_to_rfc3339_string().ok())
+    };
+    json!({
+        "id": session.id.map(|id| id.to_hex()),
+        "user_agent": session.user_agent,
+        "ip": session.ip,
+        "session_started_at": timestamp(session.session_started_at),
+        "last_used_at": timestamp(session.last_used_at),
+        "expires_at": timestamp(session.expires_at),
+        "current": session.id == Some(auth_user.session_id),
+    })
+}
+
+pub async fn list_sessions_service(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let now = DateTime::now();
+    let sessions: Vec<Value> = app_state.db
+        .list_sessions(auth_user.user_id).await?
+        .iter()
+        .filter(|session| !is_expired(session, now))
+        .map(|session| session_json(session, &auth_user))
+        .collect();
+    Ok(success_response("Sessions retrieved.", StatusCode::OK, sessions))
+}
+
+// Access tokens issued for the session stop working on AuthUser-protected routes right away.
+pub async fn revoke_session_service(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    session_id: String
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let session_id = Converter::string_to_bson(session_id)?;
+    let deleted = app_state.db.delete_session(auth_user.user_id, session_id).await?;
+    if deleted == 0 {
+        return Err(AppError::SessionNotFound);
+    }
+    record_revocation(&app_state, &auth_user, &client).await;
+    Ok(success_response("Session revoked.", StatusCode::OK, ()))
+}
+
+// Logs the user out everywhere, optionally except for the session making the request.
+pub async fn revoke_all_sessions_service(
+    State(app_state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    client: ClientInfo,
+    keep_current: bool
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let keep = if keep_current { Some(auth_user.session_id) } else { None };
+    let revoked = app_state.db.delete_user_sessions(auth_user.user_id, keep).await?;
+    if revoked > 0 {
+        record_revocation(&app_state, &auth_user, &client).await;
+    }
+    Ok(success_response("Sessions revoked.", StatusCode::OK, json!({ "revoked": revoked })))
+}
+
+async fn record_revocation(app_state: &AppState, auth_user: &AuthUser, client: &ClientInfo) {
+    let user_id = Some(auth_user.user_id);
+    let event_type = AuditEventType::SESSION_REVOKED;
+    let event = audit::action_event(event_type, user_id, None, user_id, Some(client));
+    audit::record_event(app_state, event).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{ app_state, client, login, verified_user, PASSWORD };
+    use crate::utils::jwt::decode_access_token;
+    use super::*;
+
+    // Logs in and returns the tokens, with the user behind the access token.
+    async fn session(app_state: &Arc<AppState>) -> (String, String, AuthUser) {
+        verified_user(app_state, "ann@example.com").await;
+        let (_, Json(body)) = login(app_state, "ann@example.com", PASSWORD).await.unwrap();
+        let access_token = body["data"]["access_token"].as_str().unwrap().to_string();
+        let refresh_token = body["data"]["refresh_token"].as_str().unwrap().to_string();
+        let secret = &app_state.config.session.jwt_secret;
+        let claims = decode_access_token(&access_token, secret).unwrap();
+        let auth_user = AuthUser {
+            user_id: Converter::string_to_bson(claims.user_id).unwrap(),
+            session_id: Converter::string_to_bson(claims.session_id.unwrap()).unwrap(),
+            grants: claims.grants,
+        };
+        (access_token, refresh_token, auth_user)
+    }
+
+    async fn refresh(
+        app_state: &Arc<AppState>,
+        refresh_token: &str
+    ) -> Result<(StatusCode, Json<Value>), AppError> {
+        refresh_token_service(State(app_state.clone()), client(), refresh_token.to_string()).await
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_the_refresh_token() {
+        let app_state = app_state(&[]).await;
+        let (_, refresh_token, _) = session(&app_state).await;
+        let (_, Json(body)) = refresh(&app_state, &refresh_token).await.unwrap();
+        let new_refresh_token = body["new_refresh_token"].as_str().unwrap();
+        assert_ne!(new_refresh_token, refresh_token);
+
+        assert!(matches!(refresh(&app_state, &refresh_token).await, Err(AppError::InvalidToken)));
+        let (status, _) = refresh(&app_state, new_refresh_token).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn refresh_rejects_access_tokens() {
+        let app_state = app_state(&[]).await;
+        let (access_token, _, _) = session(&app_state).await;
+        assert!(matches!(refresh(&app_state, &access_token).await, Err(AppError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn refresh_stores_the_start_of_legacy_sessions() {
+        let app_state = app_state(&[]).await;
+        let (_, refresh_token, auth_user) = session(&app_state).await;
+        let mut legacy = app_state.db.get_session(auth_user.session_id).await.unwrap().unwrap();
+        legacy.session_started_at = None;
+        app_state.db.delete_session(auth_user.user_id, auth_user.session_id).await.unwrap();
+        app_state.db.store_refresh_token(legacy).await.unwrap();
+
+        let (status, _) = refresh(&app_state, &refresh_token).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let session = app_state.db.get_session(auth_user.session_id).await.unwrap().unwrap();
+        assert!(session.session_started_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn revoked_sessions_can_not_be_refreshed() {
+        let app_state = app_state(&[]).await;
+        let (_, refresh_token, auth_user) = session(&app_state).await;
+        let session_id = auth_user.session_id.to_hex();
+        let app = State(app_state.clone());
+        let (status, _) = revoke_session_service(app, auth_user.clone(), client(), session_id)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert!(matches!(refresh(&app_state, &refresh_token).await, Err(AppError::InvalidToken)));
+
+        let session_id = auth_user.session_id.to_hex();
+        let app = State(app_state);
+        let result = revoke_session_service(app, auth_user, client(), session_id).await;
+        assert!(matches!(result, Err(AppError::SessionNotFound)));
+    }
+}
@@ -1,6 +1,7 @@
-use std::sync::Arc;
+use std::{ sync::{ Arc, OnceLock }, time::Duration };
+
+use mongodb::bson::{ oid::ObjectId, DateTime };
 
-use axum::response::Result;
 use axum::extract::Json;
 use axum::{ http::StatusCode, extract::State };
 
@@ -8,27 +9,38 @@ use crate::AppState;
 use crate::models::user_model::UserBuilder;
 use crate::utils::form_data::LogoutForm;
 use crate::models::refresh_token_model::RefreshToken;
+use crate::models::audit_event_model::AuditEventType;
 use crate::models::response_model::ResponseBuilder;
+use crate::models::error_model::AppError;
+use crate::database::error::StoreError;
 use crate::{
-    models::user_model::{ User, Email, Password, LoginTypes, UserVerificationCode },
+    models::user_model::{
+        User,
+        UserView,
+        Email,
+        Password,
+        LoginTypes,
+        UserVerificationCode,
+        CodePurpose,
+        ADMIN_ROLE,
+    },
     utils::form_data::LoginForm,
     utils::{ obj_id_converter::Converter, form_data::{ VerificationCodeForm, RegisterForm } },
-    utils::{ jwt::sign_jwt, form_data::ManualLoginForm },
+    utils::form_data::ResetPasswordForm,
+    utils::{ jwt::{ sign_jwt, Grants, TokenType }, form_data::ManualLoginForm, password_hasher },
 };
 
 use serde::{ Serialize };
 use serde_json::{ json, Value };
-use bcrypt;
 use lettre::message::header::ContentType;
+use lettre::address::AddressError;
 use lettre::transport::smtp::authentication::Credentials;
-use lettre::{ Message, SmtpTransport, Transport };
+use lettre::{ AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor };
 use rand::distributions::Alphanumeric;
 use rand::{ thread_rng, Rng };
-use crate::config::config::Config;
-
-pub fn error_response(message: &str, status_code: StatusCode) -> (StatusCode, Json<Value>) {
-    ResponseBuilder::<Value>::new(status_code).message(message).build()
-}
+use crate::services::{ audit, login_protection };
+use crate::utils::client_info::ClientInfo;
+use crate::utils::google_id_token::verify_id_token;
 
 pub fn success_response<T: Serialize>(
     message: &str,
@@ -38,153 +50,387 @@ pub fn success_response<T: Serialize>(
     ResponseBuilder::new(status_code).message(message).data(data).build()
 }
 
+// Hashing and verifying take hundreds of milliseconds of CPU, so both run on the hashing pool.
+async fn hash_password(app_state: &AppState, password: Password) -> Result<Password, AppError> {
+    let hash_config = app_state.config.password_hash.clone();
+    let hashed_password = app_state.hashing_pool.run(move || password.hash(&hash_config)).await?;
+    Ok(hashed_password?)
+}
+
+pub async fn verify_password(
+    app_state: &AppState,
+    password: &Password,
+    hashed: &Password
+) -> Result<bool, AppError> {
+    let (password, hashed) = (password.clone(), hashed.clone());
+    let is_verified = app_state.hashing_pool.run(move || password.verify(&hashed)).await?;
+    Ok(is_verified?)
+}
+
+// Stand-in hash checked when a login has no real one, so that unknown emails take as long to
+// reject as wrong passwords. Made on first use with the configured algorithm.
+static DUMMY_PASSWORD_HASH: OnceLock<Password> = OnceLock::new();
+
+async fn verify_dummy_password(app_state: &AppState, password: &Password) -> Result<(), AppError> {
+    let (password, hash_config) = (password.clone(), app_state.config.password_hash.clone());
+    // Only the time spent matters here, not the outcome.
+    let _ = app_state.hashing_pool.run(move || {
+        let dummy = DUMMY_PASSWORD_HASH.get_or_init(|| {
+            Password::from_stored("dummy password".to_string())
+                .hash(&hash_config)
+                .unwrap_or_else(|_| Password::from_stored(String::new()))
+        });
+        password.verify(dummy)
+    }).await?;
+    Ok(())
+}
+
 pub async fn register_user_service(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<RegisterForm>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
-    let email = Email::parse(String::from(&form.email))?;
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let email = form.email.clone();
+    let result = register_user(State(app_state.clone()), Json(form)).await;
+    audit::record_attempt(&app_state, AuditEventType::REGISTER, &client, &email, &result).await;
+    match result {
+        // Answered like a new registration, so the response says nothing about whether the
+        // address was taken.
+        Err(AppError::EmailAlreadyExists) if app_state.config.server.hardened_auth => {
+            Ok(registration_pending_response())
+        }
+        result => result,
+    }
+}
 
-    // check if email exists
-    let email_exist = app_state.db.get_user_by_email(email.as_str().clone());
+async fn register_user(
+    State(app_state): State<Arc<AppState>>,
+    Json(form): Json<RegisterForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    // Without email the account could never be verified, so it is not created at all.
+    if app_state.config.smtp.is_none() {
+        return Err(AppError::FeatureDisabled("Email"));
+    }
+    let email = Email::parse(String::from(&form.email), &app_state.config.email)?;
+    email.check_domain(&app_state.config.email)?;
+    let name = form.name.clone();
+    let password = Password::parse(
+        String::from(&form.password),
+        &app_state.config.password_policy,
+        &[email.local_part(), &name]
+    )?;
+    let hashed_password = hash_password(&app_state, password).await?;
+    let cloned_email = email.clone();
+    let new_user = UserBuilder::new(name, email, LoginTypes::MANUAL)
+        .password(hashed_password)
+        .is_verified(false)
+        .build();
 
-    if let Some(_) = email_exist.unwrap() {
-        Err(error_response("Email already exist.", StatusCode::BAD_REQUEST))
-    } else {
-        let name = form.name.clone();
-        let password = Password::parse(String::from(&form.password))?;
-        let hashed_password = Password::hash(&password);
-        let cloned_email = email.clone();
-        let new_user = UserBuilder::new(name, email, LoginTypes::MANUAL)
-            .password(hashed_password.unwrap())
-            .is_verified(false)
-            .build();
-
-        let _ = smtp_service(State(app_state.clone()), cloned_email);
-        match app_state.db.create_user(&new_user) {
-            Ok(_) =>
-                Ok(success_response("User created successfully!", StatusCode::CREATED, new_user)),
-            Err(_) => Err(error_response("Failed creating user", StatusCode::BAD_REQUEST)),
+    // The unique index on email decides which of two concurrent registrations wins.
+    let hardened = app_state.config.server.hardened_auth;
+    match app_state.db.create_user(&new_user).await {
+        Ok(user_id) => {
+            if let Err(err) = smtp_service(State(app_state.clone()), cloned_email).await {
+                log::error!("Failed sending the verification code of user {}: {}", user_id, err);
+            }
+            if hardened {
+                return Ok(registration_pending_response());
+            }
+            let new_user = User { id: Some(user_id), ..new_user };
+            let data = UserView::from(&new_user);
+            Ok(success_response("User created successfully!", StatusCode::CREATED, data))
+        }
+        // When hardened, the owner is emailed instead and the caller answers as for a new user.
+        Err(StoreError::Duplicate(_)) => {
+            if hardened {
+                if let Err(err) = account_exists_notice(&app_state, cloned_email).await {
+                    log::error!("Failed sending an account exists notice: {}", err);
+                }
+            }
+            Err(AppError::EmailAlreadyExists)
         }
+        Err(err) => Err(err.into()),
     }
 }
 
-pub fn smtp_service(
-    State(app_state): State<Arc<AppState>>,
-    receiver: Email
-) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
-    let conf = Config::init();
-    let code: String = thread_rng().sample_iter(&Alphanumeric).take(4).map(char::from).collect();
+fn registration_pending_response() -> (StatusCode, Json<Value>) {
+    success_response(
+        "Check your email to finish creating your account.",
+        StatusCode::CREATED,
+        ()
+    )
+}
 
-    // store the code to the "codes" collection with the "email" of its owner.
-    let verif_code_payload = UserVerificationCode {
+// Tells the owner of an existing account that someone tried to register with their email.
+async fn account_exists_notice(app_state: &AppState, receiver: Email) -> Result<(), AppError> {
+    send_email(
+        app_state,
+        &receiver,
+        "Your account",
+        "Someone tried to create an account with this email, but you already have one. \
+        If it was you, log in or reset your password. Otherwise you can ignore this email."
+            .to_string()
+    ).await
+}
+
+pub async fn send_email(
+    app_state: &AppState,
+    receiver: &Email,
+    subject: &str,
+    body: String
+) -> Result<(), AppError> {
+    let Some(conf) = &app_state.config.smtp else {
+        log::warn!("Not sending \"{}\" to {}, email is disabled", subject, receiver.as_str());
+        return Err(AppError::FeatureDisabled("Email"));
+    };
+    let email_error = |err: AddressError| AppError::EmailDelivery(err.to_string());
+    let mut builder = Message::builder().from(conf.from.clone());
+    if let Some(reply_to) = &conf.reply_to {
+        builder = builder.reply_to(reply_to.clone());
+    }
+    let email = builder
+        .to(receiver.as_str().parse().map_err(email_error)?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)
+        .map_err(|err| AppError::EmailDelivery(err.to_string()))?;
+
+    let creds = Credentials::new(conf.username.clone(), conf.password.expose().to_string());
+
+    // Open a remote connection to the SMTP server
+    let mailer = AsyncSmtpTransport::<Tokio1Executor>
+        ::relay(&conf.host)
+        .map_err(|err| AppError::EmailDelivery(err.to_string()))?
+        .credentials(creds)
+        .build();
+
+    // Send the email
+    mailer.send(email).await.map_err(|err| AppError::EmailDelivery(err.to_string()))?;
+    Ok(())
+}
+
+// Generates a code of `length` characters for `purpose`, stores it and returns it.
+pub async fn store_code(
+    app_state: &AppState,
+    email: &Email,
+    length: usize,
+    purpose: CodePurpose,
+    expires_at: DateTime
+) -> Result<String, StoreError> {
+    let code: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect();
+    let payload = UserVerificationCode {
+        email: email.clone(),
         code: code.clone(),
-        email: receiver.clone(),
+        purpose,
+        expires_at,
     };
-    let verif_code_res = app_state.db.store_verification_code(verif_code_payload);
+    app_state.db.store_verification_code(payload).await?;
+    Ok(code)
+}
+
+pub async fn smtp_service(
+    State(app_state): State<Arc<AppState>>,
+    receiver: Email
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    let lifetime_secs = app_state.config.verification_code.verification_lifetime_secs;
+    let expires_at = DateTime::from_millis(
+        DateTime::now().timestamp_millis() + lifetime_secs * 1000
+    );
+    let verif_code_res = store_code(
+        &app_state,
+        &receiver,
+        4,
+        CodePurpose::ACCOUNT_VERIFICATION,
+        expires_at
+    ).await;
 
     match verif_code_res {
-        Ok(_) => {
-            let email = Message::builder()
-                .from("NoBody <your@domain.tld>".parse().unwrap())
-                .reply_to("Yuin <my@email.tld>".parse().unwrap())
-                .to(receiver.as_str().parse().unwrap())
-                .subject("Your code")
-                .header(ContentType::TEXT_PLAIN)
-                .body(format!("Your verification code is: {}", code))
-                .unwrap();
-
-            let creds = Credentials::new(
-                conf.google_smtp_username.into(),
-                conf.google_smtp_password.into()
-            );
-
-            // Open a remote connection to gmail
-            let mailer = SmtpTransport::relay("smtp.gmail.com").unwrap().credentials(creds).build();
-
-            // Send the email
-            match mailer.send(&email) {
-                Ok(_) => Ok(success_response("Email sent successfully!", StatusCode::OK, {})),
-                Err(_) =>
-                    Err(
-                        error_response(
-                            "Failed sending email. Please try again later.",
-                            StatusCode::BAD_REQUEST
-                        )
-                    ),
-            }
+        Ok(code) => {
+            let body = format!("Your verification code is: {}", code);
+            send_email(&app_state, &receiver, "Your code", body).await?;
+            Ok(success_response("Email sent successfully!", StatusCode::OK, ()))
         }
-        Err(_) => Err(error_response("Failed storing verification code.", StatusCode::BAD_REQUEST)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Emails a code for `reset_password_service`. Longer than verification codes since it stands in
+// for the password.
+pub async fn send_password_reset_code(app_state: &AppState, email: &Email) -> Result<(), AppError> {
+    let lifetime_secs = app_state.config.verification_code.password_reset_lifetime_secs;
+    let expires_at = DateTime::from_millis(
+        DateTime::now().timestamp_millis() + lifetime_secs * 1000
+    );
+    let code = store_code(app_state, email, 8, CodePurpose::PASSWORD_RESET, expires_at).await?;
+    send_email(
+        app_state,
+        email,
+        "Reset your password",
+        format!("You need to choose a new password. Your reset code is: {}", code)
+    ).await
+}
+
+// Sets a new password with a code from `send_password_reset_code`. Every session is revoked,
+// since whoever made the reset necessary may hold one.
+pub async fn reset_password_service(
+    State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(form): Json<ResetPasswordForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let result = reset_password(&app_state, &form).await;
+    let event_type = AuditEventType::PASSWORD_RESET;
+    audit::record_attempt(&app_state, event_type, &client, &form.email, &result).await;
+    result
+}
+
+async fn reset_password(
+    app_state: &AppState,
+    form: &ResetPasswordForm
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let email = Email::parse(form.email.clone(), &app_state.config.email)?;
+    let purpose = CodePurpose::PASSWORD_RESET;
+    let code = app_state.db
+        .get_verification_code(email.as_str(), &form.code, purpose, DateTime::now()).await?;
+    if code.is_none() {
+        return Err(AppError::InvalidVerificationCode);
     }
+    let user = app_state.db
+        .get_user_by_email(email.as_str().clone()).await?
+        .ok_or(AppError::InvalidVerificationCode)?;
+    let user_id = user.id.ok_or_else(|| AppError::Internal("User ID not found.".to_string()))?;
+
+    let password = Password::parse(
+        form.password.clone(),
+        &app_state.config.password_policy,
+        &[email.local_part(), &user.name]
+    )?;
+    let hashed_password = hash_password(app_state, password).await?;
+    app_state.db.update_user_password(user_id, &hashed_password).await?;
+    app_state.db.set_password_reset_required(user_id, false).await?;
+    app_state.db.delete_verification_codes(email.as_str(), Some(purpose)).await?;
+    app_state.db.delete_user_sessions(user_id, None).await?;
+    login_protection::clear_login_failures(app_state, &email).await?;
+    Ok(success_response("Password reset. You can now log in.", StatusCode::OK, ()))
 }
 
 // User is logged in but still need to submit the code to verify their account.
 pub async fn account_verification_service(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<VerificationCodeForm>
-) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
-    let email = Email::parse(form.email.clone()).unwrap();
-    let payload = UserVerificationCode {
-        email,
-        code: form.code.clone(),
-    };
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    let result = verify_account(&app_state, &form).await;
+    let event_type = AuditEventType::VERIFY_ACCOUNT;
+    audit::record_attempt(&app_state, event_type, &client, &form.email, &result).await;
+    result
+}
+
+async fn verify_account(
+    app_state: &AppState,
+    form: &VerificationCodeForm
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    let email = Email::parse(form.email.clone(), &app_state.config.email)?;
+    let purpose = CodePurpose::ACCOUNT_VERIFICATION;
 
     // Update is_verified data of the user if it matches
-    let res: std::result::Result<
-        Option<UserVerificationCode>,
-        String
-    > = app_state.db.get_verification_code(payload);
+    let res = app_state.db
+        .get_verification_code(email.as_str(), &form.code, purpose, DateTime::now()).await;
 
     match res {
         Ok(Some(res)) => {
             let email = res.email.as_str().to_string();
-            let update_user_res = app_state.db.update_user_verification(&email);
+            let update_user_res = app_state.db.update_user_verification(&email).await;
             match update_user_res {
                 Ok(_) => {
                     // remove the verification codes in the verif codes collection after
-                    let _ = app_state.db.delete_verification_codes(&email);
-                    Ok(success_response("Account verified!", StatusCode::OK, {}))
+                    let _ = app_state.db.delete_verification_codes(&email, Some(purpose)).await;
+                    Ok(success_response("Account verified!", StatusCode::OK, ()))
                 }
-                Err(_) =>
-                    Err(error_response("Error updating user", StatusCode::INTERNAL_SERVER_ERROR)),
+                Err(err) => Err(err.into()),
             }
         }
-        Ok(None) => Err(error_response("Wrong code. Please try again.", StatusCode::BAD_REQUEST)),
-        Err(_) => Err(error_response("Error geting verification code.", StatusCode::BAD_REQUEST)),
+        Ok(None) => Err(AppError::InvalidVerificationCode),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Deletes expired codes every `purge_interval_secs`. Mongo does it on its own with a TTL index.
+pub async fn purge_expired_verification_codes(app_state: Arc<AppState>) {
+    let purge_interval_secs = app_state.config.verification_code.purge_interval_secs;
+    let period = Duration::from_secs(purge_interval_secs.max(1));
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        match app_state.db.delete_expired_verification_codes(DateTime::now()).await {
+            Ok(0) => {}
+            Ok(purged) => log::info!("Purged {} expired verification codes", purged),
+            Err(err) => log::warn!("Failed purging expired verification codes: {}", err),
+        }
     }
 }
 
 pub async fn get_user_by_id_service(
     State(app_state): State<Arc<AppState>>,
     user_id: String
-) -> Result<Option<User>, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<Option<User>, AppError> {
     let obj_id = Converter::string_to_bson(user_id)?;
-    match app_state.db.get_user_by_id(obj_id) {
-        Ok(insert_result) => Ok(insert_result),
-        Err(_) => Err(error_response("Failed creating user.", StatusCode::BAD_REQUEST)),
-    }
+    let user = app_state.db.get_user_by_id(obj_id).await?;
+    Ok(user)
 }
 
-fn login_response(
+// Starts a new session for the user.
+async fn login_response(
     State(app_state): State<Arc<AppState>>,
-    data: User
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    data: User,
+    client: &ClientInfo
+) -> Result<(StatusCode, Json<Value>), AppError> {
     let user_id_str = match data.id {
         Some(object_id) => object_id.to_hex(),
         None => {
-            return Err(error_response("User ID not found.", StatusCode::INTERNAL_SERVER_ERROR));
+            return Err(AppError::Internal("User ID not found.".to_string()));
         }
     };
 
-    let access_token = sign_jwt(&user_id_str, 5).unwrap();
-    let refresh_token = sign_jwt(&user_id_str, 1440).unwrap();
+    let data = grant_configured_admin(&app_state, data).await?;
+    let session_id = ObjectId::new();
+    let session_id_str = session_id.to_hex();
+    let now = DateTime::now();
+    let expires_at = app_state.config.session.expires_at(now, now);
+    let refresh_lifetime_secs = (expires_at.timestamp_millis() - now.timestamp_millis()) / 1000;
+    let session_config = &app_state.config.session;
+    let access_token = sign_jwt(
+        TokenType::Access,
+        &user_id_str,
+        Some(&session_id_str),
+        &data.grants(),
+        session_config.access_token_lifetime_secs,
+        &session_config.jwt_secret
+    )?;
+    let refresh_token = sign_jwt(
+        TokenType::Refresh,
+        &user_id_str,
+        Some(&session_id_str),
+        &Grants::default(),
+        refresh_lifetime_secs,
+        &session_config.jwt_secret
+    )?;
     let refresh_token_data = RefreshToken {
-        id: None,
-        user_id: data.id.into(),
+        id: Some(session_id),
+        user_id: data.id,
         email: data.email.clone(),
         refresh_token: refresh_token.clone(),
+        user_agent: client.user_agent.clone(),
+        ip: Some(client.ip.clone()),
+        session_started_at: Some(now),
+        last_used_at: Some(now),
+        expires_at: Some(expires_at),
     };
 
-    let _ = app_state.db.store_refresh_token(refresh_token_data);
+    app_state.db.store_refresh_token(refresh_token_data).await?;
     let data =
         json!({
                 "access_token": access_token,
@@ -196,96 +442,275 @@ fn login_response(
     });
 
     let response = success_response("User logged in successfully!", StatusCode::OK, data);
-    return Ok(response);
+    Ok(response)
+}
+
+// Verified password accounts listed in ADMIN_EMAILS get the admin role on login, so the first
+// admin doesn't have to be made by hand in the database.
+async fn grant_configured_admin(app_state: &AppState, mut user: User) -> Result<User, AppError> {
+    let listed = app_state.config.server.admin_emails
+        .iter()
+        .any(|email| &Email::normalize(email, &app_state.config.email) == user.email.as_str());
+    let eligible = matches!(user.login_type, LoginTypes::MANUAL) && user.is_verified == Some(true);
+    if !listed || !eligible || user.grants().has_role(ADMIN_ROLE) {
+        return Ok(user);
+    }
+    if let Some(user_id) = user.id {
+        user.roles.push(ADMIN_ROLE.to_string());
+        app_state.db.set_user_access(user_id, &user.roles, &user.permissions).await?;
+        log::info!("Granted the admin role to user {} from ADMIN_EMAILS", user_id.to_hex());
+    }
+    Ok(user)
+}
+
+// A failed upgrade is logged and otherwise ignored: the old hash still works.
+async fn rehash_password(app_state: &AppState, user: &User, password: &Password) {
+    let user_id = match user.id {
+        Some(user_id) => user_id,
+        None => {
+            return;
+        }
+    };
+    let result = match hash_password(app_state, password.clone()).await {
+        Ok(hashed_password) => app_state.db.update_user_password(user_id, &hashed_password).await,
+        Err(err) => {
+            log::warn!("Failed rehashing password of user {}: {}", user_id.to_hex(), err);
+            return;
+        }
+    };
+    if let Err(err) = result {
+        log::warn!("Failed storing rehashed password of user {}: {}", user_id.to_hex(), err);
+    }
 }
 
-// parse the email and password when a user is found.
 pub async fn manual_login_user_service(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<ManualLoginForm>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
-    let user = app_state.db.get_user_by_email(form.email.clone());
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let email = form.email.clone();
+    let result = manual_login_user(State(app_state.clone()), &client, Json(form)).await;
+    audit::record_attempt(&app_state, AuditEventType::LOGIN, &client, &email, &result).await;
+    result
+}
+
+// parse the email and password when a user is found.
+async fn manual_login_user(
+    State(app_state): State<Arc<AppState>>,
+    client: &ClientInfo,
+    Json(form): Json<ManualLoginForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let email = Email::parse(String::from(&form.email), &app_state.config.email)?;
+    let password = Password::parse_login(String::from(&form.password))?;
+    login_protection::check_login_allowed(&app_state, &email, client).await?;
+
+    let hardened = app_state.config.server.hardened_auth;
+    let user = app_state.db.get_user_by_email(email.as_str().clone()).await;
     match user {
         Ok(Some(data)) => {
-            let password = Password::parse(String::from(&form.password))?;
             let user_data = data;
-            let user_password = user_data.password
-                .as_ref()
-                .ok_or_else(|| (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json("Can't get user password".to_string()),
-                ));
-            let email = Email::parse(String::from(&form.email))?;
-
-            let is_pw_verified = bcrypt::verify(
-                password.as_str(),
-                &user_password.unwrap().as_str()
-            );
-            if !is_pw_verified.unwrap() {
-                return Err(error_response("Wrong password.", StatusCode::BAD_REQUEST));
+            let is_pw_verified = match user_data.password.as_ref() {
+                Some(user_password) => verify_password(&app_state, &password, user_password).await?,
+                None => {
+                    if hardened {
+                        verify_dummy_password(&app_state, &password).await?;
+                    }
+                    false
+                }
+            };
+            if !is_pw_verified {
+                login_protection::record_login_failure(&app_state, &email, client).await?;
+                if hardened {
+                    return Err(AppError::InvalidCredentials);
+                }
+                return Err(AppError::WrongPassword);
+            }
+            login_protection::clear_login_failures(&app_state, &email).await?;
+
+            // Only told to someone who knows the password.
+            user_data.check_status()?;
+            if user_data.password_reset_required {
+                if let Err(err) = send_password_reset_code(&app_state, &email).await {
+                    log::error!("Failed sending a required password reset code: {}", err);
+                }
+                return Err(AppError::PasswordResetRequired);
+            }
+
+            // The plain password is only available here, so this is where hashes made with an
+            // older algorithm or weaker parameters get upgraded.
+            if let Some(user_password) = user_data.password.as_ref() {
+                let hash_config = &app_state.config.password_hash;
+                if password_hasher::needs_rehash(user_password.as_str(), hash_config) {
+                    rehash_password(&app_state, &user_data, &password).await;
+                }
             }
 
             // If user is not verified yet, send a code to their email.
             if !user_data.is_verified.unwrap_or_default() {
-                let _ = smtp_service(State(app_state), email);
-                return Err(
-                    error_response(
-                        "Verify your account first. We've sent a code to your email.",
-                        StatusCode::FORBIDDEN
-                    )
-                );
+                if let Err(err) = smtp_service(State(app_state), email).await {
+                    log::error!("Failed sending a verification code on login: {}", err);
+                }
+                return Err(AppError::AccountNotVerified);
+            }
+            login_response(State(app_state), user_data, client).await
+        }
+        Ok(None) => {
+            login_protection::record_login_failure(&app_state, &email, client).await?;
+            if hardened {
+                verify_dummy_password(&app_state, &password).await?;
+                return Err(AppError::InvalidCredentials);
             }
-            let response = login_response(State(app_state), user_data).unwrap();
-            Ok(response)
+            Err(AppError::WrongEmail)
         }
-        Ok(None) => Err(error_response("Wrong email.", StatusCode::BAD_REQUEST)),
-        Err(_) =>
-            Err(error_response("Handle this mongo error.", StatusCode::INTERNAL_SERVER_ERROR)),
+        Err(err) => Err(err.into()),
     }
 }
 
 pub async fn login_google_user_service(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<LoginForm>
-) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
-    let name = form.name.clone();
-    let email = Email::parse(String::from(&form.email)).unwrap();
-    let email_str = email.as_str().clone();
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let email = form.email.clone();
+    let result = login_google_user(State(app_state.clone()), &client, Json(form)).await;
+    let event_type = AuditEventType::GOOGLE_LOGIN;
+    audit::record_attempt(&app_state, event_type, &client, &email, &result).await;
+    result
+}
 
-    // Note: Add verify id_token here in the future
+async fn login_google_user(
+    State(app_state): State<Arc<AppState>>,
+    client: &ClientInfo,
+    Json(form): Json<LoginForm>
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let google = app_state.config.google
+        .as_ref()
+        .ok_or(AppError::FeatureDisabled("Google login"))?;
+    // The account is the one Google vouches for, whatever email the form names.
+    let identity = verify_id_token(&form.id_token, google).await?;
+    let name = identity.name.unwrap_or_else(|| form.name.clone());
+    let email = Email::parse(identity.email, &app_state.config.email)?;
+    let email_str = email.as_str().clone();
 
-    let user = app_state.db.get_user_by_email(email_str);
+    let user = app_state.db.get_user_by_email(email_str).await?;
 
-    if let Some(data) = user.unwrap() {
-        let response = login_response(State(app_state.clone()), data).unwrap();
-        return Ok(response);
+    if let Some(data) = user {
+        data.check_status()?;
+        return login_response(State(app_state.clone()), data, client).await;
     }
 
+    email.check_domain(&app_state.config.email)?;
     let new_user_payload = UserBuilder::new(name, email, LoginTypes::GOOGLE)
         .is_verified(true)
         .build();
-    let new_user = app_state.db.create_user(&new_user_payload);
-    let new_user_details = get_user_by_id_service(
-        State(app_state.clone()),
-        new_user.unwrap().inserted_id.to_string()
-    ).await.unwrap();
+    let new_user_details = match app_state.db.create_user(&new_user_payload).await {
+        Ok(new_user) => get_user_by_id_service(State(app_state.clone()), new_user.to_hex()).await?,
+        // Another request created the same account in the meantime.
+        Err(StoreError::Duplicate(_)) => {
+            app_state.db.get_user_by_email(new_user_payload.email.as_str().clone()).await?
+        }
+        Err(err) => {
+            return Err(err.into());
+        }
+    };
 
     if let Some(data) = new_user_details {
-        let response = login_response(State(app_state.clone()), data).unwrap();
-        Ok(response)
+        login_response(State(app_state.clone()), data, client).await
     } else {
-        Err(error_response("User does not exist.", StatusCode::BAD_REQUEST))
+        Err(AppError::UserNotFound)
     }
 }
 
+// Only logouts that end a session are recorded; unknown tokens say nothing about who sent them.
 pub async fn logout_user_service(
     State(app_state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(form): Json<LogoutForm>
-) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
-    let res = app_state.db.delete_refresh_token(form.refresh_token);
+) -> Result<(StatusCode, Json<serde_json::Value>), AppError> {
+    let session = app_state.db.get_session_by_refresh_token(&form.refresh_token).await?;
+    let res = app_state.db.delete_refresh_token(form.refresh_token).await;
     match res {
-        Ok(_) => Ok(success_response("User logged out successfully!", StatusCode::OK, {})),
-        Err(_) =>
-            Err(error_response("Error Invalidating Token", StatusCode::INTERNAL_SERVER_ERROR)),
+        Ok(deleted) => {
+            if let Some(session) = session.filter(|_| deleted > 0) {
+                // The refresh token vouches for the user.
+                let event = audit::action_event(
+                    AuditEventType::LOGOUT,
+                    session.user_id,
+                    Some(session.email.as_str().clone()),
+                    session.user_id,
+                    Some(&client)
+                );
+                audit::record_event(&app_state, event).await;
+            }
+            Ok(success_response("User logged out successfully!", StatusCode::OK, ()))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{
+        app_state,
+        app_state_without_email,
+        login,
+        register,
+        verified_user,
+        PASSWORD,
+    };
+    use super::*;
+
+    #[tokio::test]
+    async fn register_creates_an_unverified_user() {
+        let app_state = app_state(&[]).await;
+        let (status, Json(body)) = register(&app_state, " Ann@Example.com ").await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["data"]["email"], "ann@example.com");
+
+        let user = app_state.db.get_user_by_email("ann@example.com".to_string()).await.unwrap();
+        let user = user.unwrap();
+        assert_eq!(user.is_verified, Some(false));
+        assert_ne!(user.password.unwrap().as_str(), PASSWORD);
+    }
+
+    #[tokio::test]
+    async fn register_rejects_a_taken_email() {
+        let app_state = app_state(&[]).await;
+        let (status, _) = register(&app_state, "ann@example.com").await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let result = register(&app_state, "ANN@example.com").await;
+        assert!(matches!(result, Err(AppError::EmailAlreadyExists)));
+    }
+
+    #[tokio::test]
+    async fn register_needs_email() {
+        let app_state = app_state_without_email().await;
+        let result = register(&app_state, "ann@example.com").await;
+        assert!(matches!(result, Err(AppError::FeatureDisabled("Email"))));
+    }
+
+    #[tokio::test]
+    async fn login_returns_tokens_for_a_verified_user() {
+        let app_state = app_state(&[]).await;
+        verified_user(&app_state, "ann@example.com").await;
+        let (status, Json(body)) = login(&app_state, "ann@example.com", PASSWORD).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["data"]["access_token"].is_string());
+        assert!(body["data"]["refresh_token"].is_string());
+    }
+
+    #[tokio::test]
+    async fn login_rejects_unverified_users_and_wrong_credentials() {
+        let app_state = app_state(&[]).await;
+        let (status, _) = register(&app_state, "ann@example.com").await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let result = login(&app_state, "ann@example.com", PASSWORD).await;
+        assert!(matches!(result, Err(AppError::AccountNotVerified)));
+
+        verified_user(&app_state, "bob@example.com").await;
+        let result = login(&app_state, "bob@example.com", "Wr0ng!pass").await;
+        assert!(matches!(result, Err(AppError::WrongPassword)));
+        let result = login(&app_state, "nobody@example.com", PASSWORD).await;
+        assert!(matches!(result, Err(AppError::WrongEmail)));
     }
 }
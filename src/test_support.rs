@@ -0,0 +1,89 @@
+// Setup shared by the service tests: an `AppState` on the in-memory store.
+use std::{ sync::Arc, time::Duration };
+
+use axum::{ extract::{ Json, State }, http::StatusCode };
+use serde_json::Value;
+
+use crate::AppState;
+use crate::config::{ config::Config, source::ConfigSource };
+use crate::database::init_store;
+use crate::models::{ error_model::AppError, user_model::Email };
+use crate::rate_limit::RateLimiter;
+use crate::services::user::{ manual_login_user_service, register_user_service };
+use crate::utils::{
+    client_info::ClientInfo,
+    form_data::{ ManualLoginForm, RegisterForm },
+    hashing_pool::HashingPool,
+};
+
+pub const PASSWORD: &str = "Str0ng!pass";
+
+const BASE_SETTINGS: [(&str, &str); 4] = [
+    ("DATABASE_BACKEND", "memory"),
+    ("JWT_SECRET", "0123456789abcdef0123456789abcdef"),
+    // The smallest Argon2 cost keeps the tests fast.
+    ("ARGON2_MEMORY_KIB", "8"),
+    ("ARGON2_ITERATIONS", "1"),
+];
+
+// Registration needs email. Nothing listens on the host, so sends fail right away.
+const EMAIL_SETTINGS: [(&str, &str); 3] = [
+    ("SMTP_USERNAME", "auth@example.com"),
+    ("SMTP_PASSWORD", "secret"),
+    ("SMTP_HOST", "127.0.0.1"),
+];
+
+// Later settings win, so tests override the defaults by passing the same key.
+pub async fn app_state(settings: &[(&str, &str)]) -> Arc<AppState> {
+    let mut values = BASE_SETTINGS.to_vec();
+    values.extend_from_slice(&EMAIL_SETTINGS);
+    values.extend_from_slice(settings);
+    build_app_state(&values).await
+}
+
+pub async fn app_state_without_email() -> Arc<AppState> {
+    build_app_state(&BASE_SETTINGS).await
+}
+
+async fn build_app_state(values: &[(&str, &str)]) -> Arc<AppState> {
+    let source = ConfigSource::from_values(values);
+    let config = Config::init(&source);
+    source.finish().expect("invalid test settings");
+    let db = init_store(&config.database, &config.email, Duration::ZERO).await.unwrap();
+    let hashing_pool = HashingPool::new(4, Duration::from_secs(10));
+    let rate_limiter = RateLimiter::init(&config.rate_limit).await.unwrap();
+    Arc::new(AppState { db, config, hashing_pool, rate_limiter })
+}
+
+pub fn client() -> ClientInfo {
+    ClientInfo { ip: "203.0.113.7".to_string(), user_agent: Some("tests".to_string()) }
+}
+
+pub async fn register(
+    app_state: &Arc<AppState>,
+    email: &str
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let form = RegisterForm {
+        name: "Ann".to_string(),
+        email: email.to_string(),
+        password: PASSWORD.to_string(),
+    };
+    register_user_service(State(app_state.clone()), client(), Json(form)).await
+}
+
+// Registers an account and verifies it, as the emailed code would.
+pub async fn verified_user(app_state: &Arc<AppState>, email: &str) {
+    let (status, _) = register(app_state, email).await.unwrap();
+    assert_eq!(status, StatusCode::CREATED);
+    let email = Email::parse(email.to_string(), &app_state.config.email).unwrap();
+    app_state.db.update_user_verification(email.as_str()).await.unwrap();
+}
+
+pub async fn login(
+    app_state: &Arc<AppState>,
+    email: &str,
+    password: &str
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let form = ManualLoginForm { email: email.to_string(), password: password.to_string() };
+    manual_login_user_service(State(app_state.clone()), client(), Json(form)).await
+}
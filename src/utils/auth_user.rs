@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ FromRequestParts, Request, State },
+    http::{ header::AUTHORIZATION, request::Parts },
+    middleware::Next,
+    response::{ IntoResponse, Response },
+};
+use mongodb::bson::{ oid::ObjectId, DateTime };
+
+use crate::AppState;
+use crate::models::error_model::AppError;
+use crate::utils::jwt::{ decode_access_token, get_token, Grants };
+
+// The user behind the bearer access token of a request.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: ObjectId,
+    pub session_id: ObjectId,
+    // As of when the token was issued.
+    pub grants: Grants,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>
+    ) -> Result<Self, Self::Rejection> {
+        // Already decoded by a guard.
+        if let Some(auth_user) = parts.extensions.get::<AuthUser>() {
+            return Ok(auth_user.clone());
+        }
+        let auth_header = parts.headers.get(AUTHORIZATION).ok_or(AppError::MissingAuthHeader)?;
+        let secret = &state.config.session.jwt_secret;
+        let claims = decode_access_token(&get_token(auth_header)?, secret)?;
+        let user_id = ObjectId::parse_str(&claims.user_id).map_err(|_| AppError::InvalidToken)?;
+        let session_id = claims.session_id
+            .and_then(|id| ObjectId::parse_str(id).ok())
+            .ok_or(AppError::InvalidToken)?;
+
+        // Access tokens stop working as soon as their session is revoked or the user blocked,
+        // instead of when they expire.
+        let session = state.db.get_session(session_id).await?.ok_or(AppError::InvalidToken)?;
+        if session.user_id != Some(user_id) {
+            return Err(AppError::InvalidToken);
+        }
+        if session.expires_at.is_some_and(|expires_at| expires_at <= DateTime::now()) {
+            return Err(AppError::ExpiredToken);
+        }
+        let user = state.db.get_user_by_id(user_id).await?.ok_or(AppError::InvalidToken)?;
+        user.check_status()?;
+        Ok(AuthUser { user_id, session_id, grants: claims.grants })
+    }
+}
+
+// What a route asks of the caller's access token. Applied with `require`, e.g.
+// `guard(RequireRole("admin"))` in `route.rs`.
+#[derive(Debug, Clone, Copy)]
+pub enum Guard {
+    RequireRole(&'static str),
+    RequirePermission(&'static str),
+}
+
+impl Guard {
+    fn allows(&self, grants: &Grants) -> bool {
+        match self {
+            Guard::RequireRole(role) => grants.has_role(role),
+            Guard::RequirePermission(permission) => grants.has_permission(permission),
+        }
+    }
+}
+
+// State for the `require` middleware.
+#[derive(Clone)]
+pub struct Guarded {
+    app_state: Arc<AppState>,
+    guard: Guard,
+}
+
+impl Guarded {
+    pub fn new(app_state: Arc<AppState>, guard: Guard) -> Self {
+        Guarded { app_state, guard }
+    }
+}
+
+// Rejects requests whose token lacks what the guard asks for. The decoded user is kept in the
+// request, so handlers extracting `AuthUser` don't decode the token again.
+pub async fn require(State(guarded): State<Guarded>, request: Request, next: Next) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let auth_user = match AuthUser::from_request_parts(&mut parts, &guarded.app_state).await {
+        Ok(auth_user) => auth_user,
+        Err(err) => {
+            return err.into_response();
+        }
+    };
+    if !guarded.guard.allows(&auth_user.grants) {
+        return AppError::Forbidden.into_response();
+    }
+    parts.extensions.insert(auth_user);
+    next.run(Request::from_parts(parts, body)).await
+}
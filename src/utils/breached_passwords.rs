@@ -0,0 +1,212 @@
+use std::{ fmt, fs::{ self, File }, io::{ self, BufRead, BufReader }, path::{ Path, PathBuf } };
+
+use sha1::{ Digest, Sha1 };
+
+// Offline set of breached password hashes, kept in a bloom filter so a corpus of hundreds of
+// millions of entries fits in memory (about 1.8 GB for the full HIBP list at a 0.1% false
+// positive rate). A false positive only means a good password gets rejected, never the reverse.
+pub struct BreachedPasswords {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+    len: u64,
+}
+
+impl fmt::Debug for BreachedPasswords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BreachedPasswords")
+            .field("entries", &self.len)
+            .field("bytes", &(self.bits.len() * 8))
+            .field("num_hashes", &self.num_hashes)
+            .finish()
+    }
+}
+
+impl BreachedPasswords {
+    // Loads the output of the HIBP password downloader, either a single file of `SHA1:COUNT`
+    // lines or a directory of range files. Range files are named after the 5 character hash
+    // prefix (e.g. `21BD1.txt`) and hold `SUFFIX:COUNT` lines with the other 35 characters.
+    // Lines that don't hold a hash are skipped, but a list without any is an error since it
+    // most likely isn't in either format.
+    pub fn load(path: &Path, false_positive_rate: f64) -> io::Result<BreachedPasswords> {
+        let files = if path.is_dir() { list_files(path)? } else { vec![path.to_path_buf()] };
+
+        // First pass sizes the filter, the second one fills it.
+        let mut entries = 0;
+        for file in &files {
+            read_hashes(file, |_| entries += 1)?;
+        }
+        if entries == 0 {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidData, "no SHA-1 password hashes found")
+            );
+        }
+
+        let mut filter = BreachedPasswords::with_capacity(entries, false_positive_rate);
+        for file in &files {
+            read_hashes(file, |hash| filter.insert(&hash))?;
+        }
+        Ok(filter)
+    }
+
+    fn with_capacity(entries: u64, false_positive_rate: f64) -> BreachedPasswords {
+        let entries = entries.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = ((-entries * false_positive_rate.ln()) / (ln2 * ln2)).ceil().max(64.0);
+        let num_hashes = ((num_bits / entries) * ln2).round().max(1.0) as u32;
+        let num_bits = num_bits as u64;
+
+        BreachedPasswords {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+            len: 0,
+        }
+    }
+
+    pub fn contains(&self, password: &str) -> bool {
+        let hash: [u8; 20] = Sha1::digest(password.as_bytes()).into();
+        self.bit_indexes(&hash).all(|index| self.bits[(index / 64) as usize] & bit(index) != 0)
+    }
+
+    fn insert(&mut self, hash: &[u8; 20]) {
+        let indexes: Vec<u64> = self.bit_indexes(hash).collect();
+        for index in indexes {
+            self.bits[(index / 64) as usize] |= bit(index);
+        }
+        self.len += 1;
+    }
+
+    // SHA-1 output is already uniformly distributed, so two halves of it serve as the base
+    // hashes for double hashing instead of running extra hash functions.
+    fn bit_indexes<'a>(&'a self, hash: &[u8; 20]) -> impl Iterator<Item = u64> + 'a {
+        let h1 = u64::from_be_bytes(hash[0..8].try_into().unwrap_or_default());
+        let h2 = u64::from_be_bytes(hash[8..16].try_into().unwrap_or_default());
+        (0..self.num_hashes as u64).map(move |i| {
+            h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits
+        })
+    }
+}
+
+fn bit(index: u64) -> u64 {
+    1 << (index % 64)
+}
+
+fn list_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+// Calls `found` with every hash in `file`.
+fn read_hashes(file: &Path, mut found: impl FnMut([u8; 20])) -> io::Result<()> {
+    let prefix = range_prefix(file);
+    for line in BufReader::new(File::open(file)?).lines() {
+        if let Some(hash) = parse_line(&line?, prefix.as_deref()) {
+            found(hash);
+        }
+    }
+    Ok(())
+}
+
+// The hash prefix a range file is named after.
+fn range_prefix(file: &Path) -> Option<String> {
+    let stem = file.file_stem()?.to_str()?;
+    let is_prefix = stem.len() == 5 && stem.chars().all(|c| c.is_ascii_hexdigit());
+    is_prefix.then(|| stem.to_string())
+}
+
+fn parse_line(line: &str, prefix: Option<&str>) -> Option<[u8; 20]> {
+    let field = line.split(':').next()?.trim();
+    let hex = match prefix {
+        Some(prefix) if field.len() == 35 => format!("{}{}", prefix, field),
+        _ => field.to_string(),
+    };
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut hash = [0u8; 20];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD_SHA1: &str = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8";
+    const HUNTER2_SHA1: &str = "F3BBBD66A63D4BF1747940578EC3D0103530E21D";
+
+    // A fresh directory per test, since tests run in parallel.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(
+            format!("breached-passwords-{}-{}", std::process::id(), name)
+        );
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn loads_a_file_of_full_hashes() {
+        let file = temp_dir("full").join("pwned-passwords.txt");
+        let lines = format!("{}:3861493\n{}:17043\nnot a hash\n", PASSWORD_SHA1, HUNTER2_SHA1);
+        fs::write(&file, lines).unwrap();
+
+        let list = BreachedPasswords::load(&file, 0.001).unwrap();
+        assert_eq!(list.len, 2);
+        assert!(list.contains("password"));
+        assert!(list.contains("hunter2"));
+        assert!(!list.contains("Correct horse battery staple"));
+    }
+
+    #[test]
+    fn loads_a_directory_of_range_files() {
+        let dir = temp_dir("ranges");
+        fs::write(dir.join("5BAA6.txt"), format!("{}:3861493\n", &PASSWORD_SHA1[5..])).unwrap();
+        fs::write(dir.join("F3BBB.txt"), format!("{}:17043\n", &HUNTER2_SHA1[5..])).unwrap();
+
+        let list = BreachedPasswords::load(&dir, 0.001).unwrap();
+        assert!(list.contains("password"));
+        assert!(list.contains("hunter2"));
+        assert!(!list.contains("Correct horse battery staple"));
+    }
+
+    #[test]
+    fn rejects_a_list_without_hashes() {
+        let file = temp_dir("empty").join("passwords.txt");
+        fs::write(&file, "password\nhunter2\n").unwrap();
+        let err = BreachedPasswords::load(&file, 0.001).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn stays_near_the_false_positive_rate() {
+        let mut list = BreachedPasswords::with_capacity(10_000, 0.01);
+        for i in 0..10_000 {
+            list.insert(&Sha1::digest(format!("breached-{}", i)).into());
+        }
+        assert!((0..10_000).all(|i| list.contains(&format!("breached-{}", i))));
+        let false_positives = (0..10_000)
+            .filter(|i| list.contains(&format!("fine-{}", i)))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn parses_range_suffixes_only_with_a_prefix() {
+        let suffix = format!("{}:12", &PASSWORD_SHA1[5..]);
+        assert!(parse_line(&suffix, Some("5BAA6")).is_some());
+        assert!(parse_line(&suffix, None).is_none());
+        assert!(parse_line(&format!("{}:12", PASSWORD_SHA1), Some("5BAA6")).is_some());
+        assert_eq!(range_prefix(Path::new("ranges/5BAA6.txt")).as_deref(), Some("5BAA6"));
+        assert_eq!(range_prefix(Path::new("pwned-passwords.txt")), None);
+    }
+}
@@ -0,0 +1,85 @@
+use std::{ net::SocketAddr, sync::Arc };
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ ConnectInfo, FromRequestParts },
+    http::{ header::USER_AGENT, request::Parts },
+};
+
+use crate::AppState;
+use crate::models::error_model::AppError;
+
+// Who is making the request, as far as we can tell.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ClientInfo {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>
+    ) -> Result<Self, Self::Rejection> {
+        let server = &state.config.server;
+        let forwarded_ip = if server.trust_proxy_headers {
+            parts.headers
+                .get("X-Forwarded-For")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| forwarded_client(value, server.trusted_proxy_hops))
+        } else {
+            None
+        };
+        let ip = match forwarded_ip {
+            Some(ip) => ip,
+            None =>
+                parts.extensions
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip().to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+        };
+        let user_agent = parts.headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        Ok(ClientInfo { ip, user_agent })
+    }
+}
+
+// Each proxy appends the address it got the request from, so the client is the entry the
+// outermost trusted proxy added, `hops` from the right. Anything further left is client input.
+fn forwarded_client(header: &str, hops: usize) -> Option<String> {
+    let entries: Vec<&str> = header.split(',').map(|ip| ip.trim()).collect();
+    let index = entries.len().checked_sub(hops.max(1))?;
+    Some(entries[index].to_string()).filter(|ip| !ip.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_the_entry_added_by_the_outermost_trusted_proxy() {
+        let header = "198.51.100.1, 203.0.113.7, 10.0.0.2";
+        assert_eq!(forwarded_client(header, 1).as_deref(), Some("10.0.0.2"));
+        assert_eq!(forwarded_client(header, 2).as_deref(), Some("203.0.113.7"));
+        assert_eq!(forwarded_client(header, 3).as_deref(), Some("198.51.100.1"));
+    }
+
+    #[test]
+    fn ignores_entries_spoofed_by_the_client() {
+        // The client sent "1.2.3.4" itself; the one trusted proxy appended the real address.
+        assert_eq!(forwarded_client("1.2.3.4, 203.0.113.7", 1).as_deref(), Some("203.0.113.7"));
+    }
+
+    #[test]
+    fn needs_an_entry_for_every_hop() {
+        assert_eq!(forwarded_client("203.0.113.7", 2), None);
+        assert_eq!(forwarded_client("203.0.113.7,", 1), None);
+        assert_eq!(forwarded_client("203.0.113.7", 0).as_deref(), Some("203.0.113.7"));
+    }
+}
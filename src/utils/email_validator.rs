@@ -0,0 +1,99 @@
+// Syntax checks for email addresses following the addr-spec of RFC 5322, with the UTF-8 local
+// parts and internationalized domains allowed by RFC 6531. Comments and folding whitespace are
+// rejected: they are legal in headers but never typed into a sign-up form.
+
+const MAX_EMAIL_LENGTH: usize = 254;
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+const MAX_DOMAIN_LENGTH: usize = 253;
+const MAX_LABEL_LENGTH: usize = 63;
+
+pub fn is_valid(email: &str) -> bool {
+    if email.len() > MAX_EMAIL_LENGTH {
+        return false;
+    }
+    match email.rsplit_once('@') {
+        Some((local, domain)) => is_valid_local_part(local) && is_valid_domain(domain),
+        None => false,
+    }
+}
+
+// atext from RFC 5322 3.2.3, extended with any non-ASCII character per RFC 6531.
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c) || !c.is_ascii()
+}
+
+fn is_valid_local_part(local: &str) -> bool {
+    if local.is_empty() || local.len() > MAX_LOCAL_PART_LENGTH {
+        return false;
+    }
+    if local.starts_with('"') {
+        return is_valid_quoted_string(local);
+    }
+    // dot-atom: atoms separated by single dots, no leading or trailing dot.
+    local.split('.').all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+}
+
+fn is_valid_quoted_string(local: &str) -> bool {
+    if local.len() < 2 || !local.ends_with('"') {
+        return false;
+    }
+    let mut chars = local[1..local.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // quoted-pair: a backslash followed by any visible character or space.
+            '\\' =>
+                match chars.next() {
+                    Some(escaped) if escaped == ' ' || !escaped.is_control() => {}
+                    _ => {
+                        return false;
+                    }
+                }
+            '"' => {
+                return false;
+            }
+            c if c.is_control() => {
+                return false;
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+// Expects the ASCII (punycode) form of the domain, see `Email::normalize`.
+fn is_valid_domain(domain: &str) -> bool {
+    if domain.starts_with('[') && domain.ends_with(']') {
+        return is_valid_address_literal(&domain[1..domain.len() - 1]);
+    }
+    if domain.is_empty() || domain.len() > MAX_DOMAIN_LENGTH {
+        return false;
+    }
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2 {
+        return false;
+    }
+    let labels_valid = labels.iter().all(|label| {
+        !label.is_empty() &&
+            label.len() <= MAX_LABEL_LENGTH &&
+            !label.starts_with('-') &&
+            !label.ends_with('-') &&
+            label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    });
+    // A top-level domain is never all digits; that would be an unbracketed IP address.
+    let tld = labels[labels.len() - 1];
+    labels_valid && !tld.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_valid_address_literal(literal: &str) -> bool {
+    match literal.strip_prefix("IPv6:") {
+        Some(ipv6) => ipv6.parse::<std::net::Ipv6Addr>().is_ok(),
+        None => literal.parse::<std::net::Ipv4Addr>().is_ok(),
+    }
+}
+
+// True when `domain` is one of `domains` or a subdomain of one.
+pub fn domain_in(domain: &str, domains: &[String]) -> bool {
+    domains
+        .iter()
+        .any(|listed| domain == listed || domain.ends_with(&format!(".{}", listed)))
+}
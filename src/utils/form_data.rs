@@ -26,7 +26,77 @@ pub struct VerificationCodeForm {
     pub code: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResetPasswordForm {
+    pub email: String,
+    pub code: String,
+    pub password: String,
+}
+
+// Confirms a deletion: the password, or the email for accounts without one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountForm {
+    pub password: Option<String>,
+    pub email: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LogoutForm {
     pub refresh_token: String,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnlockAccountForm {
+    pub email: String,
+    pub ip: Option<String>,
+}
+
+// Replaces the roles and permissions of a user.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserAccessForm {
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+// Shown to admins with the user, not to the user.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserStatusForm {
+    pub reason: Option<String>,
+}
+
+// Filters and page of GET /admin/users. Dates are RFC 3339.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserListQuery {
+    pub search: Option<String>,
+    pub login_type: Option<String>,
+    pub is_verified: Option<bool>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
+}
+
+// Filters and page of the audit event listings. Dates are RFC 3339.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEventListQuery {
+    pub user_id: Option<String>,
+    // Who performed the action, such as the admin behind an account change.
+    pub actor_id: Option<String>,
+    pub email: Option<String>,
+    pub event_type: Option<String>,
+    pub outcome: Option<String>,
+    pub ip: Option<String>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeSessionsQuery {
+    // Keeps the session of the access token making the request.
+    #[serde(default)]
+    pub keep_current: bool,
+}
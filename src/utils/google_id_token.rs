@@ -0,0 +1,59 @@
+use serde::Deserialize;
+
+use crate::config::config::GoogleConfig;
+use crate::models::error_model::AppError;
+
+// Google checks the signature and expiry of the token and returns its claims.
+const TOKEN_INFO_URL: &str = "https://oauth2.googleapis.com/tokeninfo";
+const ISSUERS: [&str; 2] = ["accounts.google.com", "https://accounts.google.com"];
+
+// The account a Google ID token was issued for.
+#[derive(Debug, Clone)]
+pub struct GoogleIdentity {
+    pub email: String,
+    pub name: Option<String>,
+}
+
+// Claims as returned by the token info endpoint, which encodes booleans as strings.
+#[derive(Debug, Deserialize)]
+struct TokenInfo {
+    iss: String,
+    aud: String,
+    email: Option<String>,
+    email_verified: Option<String>,
+    name: Option<String>,
+}
+
+// Only tokens issued by Google to this app, for a verified email, are accepted.
+pub async fn verify_id_token(
+    id_token: &str,
+    config: &GoogleConfig
+) -> Result<GoogleIdentity, AppError> {
+    if id_token.trim().is_empty() {
+        return Err(AppError::InvalidIdToken);
+    }
+    let response = reqwest::Client
+        ::new()
+        .get(TOKEN_INFO_URL)
+        .query(&[("id_token", id_token)])
+        .send().await
+        .map_err(|err| AppError::IdentityProvider(err.to_string()))?;
+    // Invalid and expired tokens are answered with 400.
+    if response.status().is_client_error() {
+        return Err(AppError::InvalidIdToken);
+    }
+    let info: TokenInfo = response
+        .error_for_status()
+        .map_err(|err| AppError::IdentityProvider(err.to_string()))?
+        .json().await
+        .map_err(|err| AppError::IdentityProvider(err.to_string()))?;
+
+    if info.aud != config.oauth_client_id || !ISSUERS.contains(&info.iss.as_str()) {
+        return Err(AppError::InvalidIdToken);
+    }
+    if info.email_verified.as_deref() != Some("true") {
+        return Err(AppError::InvalidIdToken);
+    }
+    let email = info.email.ok_or(AppError::InvalidIdToken)?;
+    Ok(GoogleIdentity { email, name: info.name })
+}
@@ -0,0 +1,42 @@
+use std::{ sync::Arc, time::Duration };
+
+use tokio::sync::Semaphore;
+
+use crate::models::error_model::AppError;
+
+// Runs CPU heavy password hashing on tokio's blocking threads so it never stalls the async
+// workers. At most `max_concurrent` jobs run at once; a request that can't get a slot within
+// `queue_timeout` is turned away with a 503 instead of piling up behind the others.
+pub struct HashingPool {
+    permits: Arc<Semaphore>,
+    queue_timeout: Duration,
+}
+
+impl HashingPool {
+    pub fn new(max_concurrent: usize, queue_timeout: Duration) -> Self {
+        HashingPool {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            queue_timeout,
+        }
+    }
+
+    pub async fn run<F, T>(&self, job: F) -> Result<T, AppError>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static
+    {
+        let permit = match
+            tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned()).await
+        {
+            Ok(Ok(permit)) => permit,
+            Ok(Err(_)) | Err(_) => {
+                return Err(AppError::Overloaded);
+            }
+        };
+
+        tokio::task
+            ::spawn_blocking(move || {
+                let _permit = permit;
+                job()
+            }).await
+            .map_err(|err| AppError::Internal(err.to_string()))
+    }
+}
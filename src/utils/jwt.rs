@@ -1,54 +1,102 @@
 use axum::http::HeaderValue;
 use std::time::SystemTime;
 use jsonwebtoken::{ encode, decode, Header, Algorithm, Validation, EncodingKey, DecodingKey };
-use crate::services::user::error_response;
+use crate::config::secret::Secret;
+use crate::models::error_model::AppError;
 use chrono::{ Utc, Duration };
+use rand::{ distributions::Alphanumeric, thread_rng, Rng };
 
 use serde::{ Serialize, Deserialize };
-use axum::{ extract::Json, http::StatusCode };
+
+// What the holder of an access token may do. Refresh tokens carry none; the current grants of
+// the user are read again on every refresh.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Grants {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
+}
+
+impl Grants {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|granted| granted == role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|granted| granted == permission)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenType {
+    Access,
+    Refresh,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    user_id: String,
-    issued_at: u64,
-    exp: u64,
+pub struct Claims {
+    pub user_id: String,
+    // Missing on tokens from before it was recorded. Those only work as refresh tokens, where the
+    // stored session is what counts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<TokenType>,
+    // The session the token was issued for. Missing on tokens from before sessions existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(flatten)]
+    pub grants: Grants,
+    pub issued_at: u64,
+    pub exp: u64,
+    // Random, so tokens signed for the same user within the same second still differ.
+    #[serde(default)]
+    jti: String,
 }
 
 pub fn sign_jwt(
+    token_type: TokenType,
     user_id: &str,
-    exp_time_mins: i64
-) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+    session_id: Option<&str>,
+    grants: &Grants,
+    exp_time_secs: i64,
+    secret: &Secret
+) -> Result<String, AppError> {
     let header = Header::new(Algorithm::HS512);
 
     let current_time = Utc::now().timestamp() as u64;
 
-    // Token expires in 1 hour
-    let expiration_time = (Utc::now() + Duration::minutes(exp_time_mins)).timestamp() as u64;
+    // Lifetimes come from the session config: short for access tokens, longer for refresh ones.
+    let expiration_time = (Utc::now() + Duration::seconds(exp_time_secs)).timestamp() as u64;
 
     let my_claims = Claims {
         user_id: String::from(user_id),
+        token_type: Some(token_type),
+        session_id: session_id.map(String::from),
+        grants: grants.clone(),
         issued_at: current_time,
         exp: expiration_time,
+        jti: thread_rng().sample_iter(&Alphanumeric).take(16).map(char::from).collect(),
     };
 
-    let token = encode(&header, &my_claims, &EncodingKey::from_secret("secret".as_ref()));
-    Ok(token.unwrap())
+    encode(&header, &my_claims, &EncodingKey::from_secret(secret.expose().as_ref())).map_err(|err|
+        AppError::Internal(err.to_string())
+    )
 }
 
 pub fn get_token(
     auth_header: &HeaderValue
-) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<String, AppError> {
     if auth_header.is_empty() {
-        return Err(error_response("No auth header.", StatusCode::BAD_REQUEST));
+        return Err(AppError::MissingAuthHeader);
     }
 
     let auth_str = auth_header
         .to_str()
-        .map_err(|_| error_response("Invalid auth header format.", StatusCode::BAD_REQUEST))
-        .unwrap();
+        .map_err(|_| AppError::InvalidAuthHeader)?;
 
     if !auth_str.starts_with("Bearer ") {
-        return Err(error_response("Invalid auth header format.", StatusCode::BAD_REQUEST));
+        return Err(AppError::InvalidAuthHeader);
     }
 
     let parts: Vec<&str> = auth_str.split_whitespace().collect();
@@ -56,34 +104,38 @@ pub fn get_token(
     if let Some(token) = parts.get(1) {
         Ok(String::from(token.to_owned()))
     } else {
-        return Err(
-            error_response(
-                "Error in getting parts of the token.",
-                StatusCode::INTERNAL_SERVER_ERROR
-            )
-        );
+        Err(AppError::InvalidAuthHeader)
+    }
+}
+
+// Refresh tokens live much longer than access tokens, so they are never accepted in their place.
+pub fn decode_access_token(access_token: &str, secret: &Secret) -> Result<Claims, AppError> {
+    let claims = decode_jwt(access_token, secret)?;
+    if claims.token_type != Some(TokenType::Access) {
+        return Err(AppError::InvalidToken);
     }
+    Ok(claims)
 }
 
-pub fn validate_jwt(access_token: &str) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
-    let decoding_key = DecodingKey::from_secret("secret".as_ref());
-    let mut validation = Validation::new(Algorithm::HS512);
+pub fn decode_jwt(access_token: &str, secret: &Secret) -> Result<Claims, AppError> {
+    let decoding_key = DecodingKey::from_secret(secret.expose().as_ref());
+    let validation = Validation::new(Algorithm::HS512);
 
-    let token_data = match decode::<Claims>(&access_token, &decoding_key, &validation) {
+    let token_data = match decode::<Claims>(access_token, &decoding_key, &validation) {
         Ok(token_data) => token_data,
-        Err(err) => {
-            return Err(error_response("Invalid access token.", StatusCode::UNAUTHORIZED));
+        Err(_) => {
+            return Err(AppError::InvalidToken);
         }
     };
 
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("Time went backwards")
+        .map_err(|err| AppError::Internal(err.to_string()))?
         .as_secs();
 
     if token_data.claims.exp < current_time {
-        return Err(error_response("Expired access token.", StatusCode::UNAUTHORIZED));
+        return Err(AppError::ExpiredToken);
     }
 
-    Ok(String::from(token_data.claims.user_id))
+    Ok(token_data.claims)
 }
@@ -1,3 +1,11 @@
 pub mod form_data;
 pub mod jwt;
 pub mod obj_id_converter;
+pub mod email_validator;
+pub mod password_policy;
+pub mod breached_passwords;
+pub mod password_hasher;
+pub mod hashing_pool;
+pub mod client_info;
+pub mod auth_user;
+pub mod google_id_token;
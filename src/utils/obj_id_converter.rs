@@ -1,17 +1,15 @@
-use axum::{ response::Result, Json };
 use mongodb::bson::oid::ObjectId;
-use reqwest::StatusCode;
 
-use crate::services::user::error_response;
+use crate::models::error_model::AppError;
 
 pub struct Converter;
 
 impl Converter {
-    pub fn string_to_bson(id: String) -> Result<ObjectId, (StatusCode, Json<serde_json::Value>)> {
+    pub fn string_to_bson(id: String) -> Result<ObjectId, AppError> {
         let obj_id = match ObjectId::parse_str(&id) {
             Ok(obj_id) => obj_id,
             Err(_) => {
-                return Err(error_response("Invalid ID format.", StatusCode::BAD_REQUEST));
+                return Err(AppError::InvalidId);
             }
         };
         Ok(obj_id)
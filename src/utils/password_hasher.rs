@@ -0,0 +1,127 @@
+use std::fmt;
+
+use argon2::{
+    password_hash::{ PasswordHash, PasswordHasher, PasswordVerifier, SaltString },
+    Algorithm,
+    Argon2,
+    Params,
+    Version,
+};
+use bcrypt::BcryptError;
+use rand::{ thread_rng, RngCore };
+
+use crate::config::config::{ PasswordHashConfig, HashAlgorithm };
+
+// bcrypt only looks at the first 72 bytes of its input.
+pub const BCRYPT_MAX_PASSWORD_BYTES: usize = 72;
+
+#[derive(Debug)]
+pub enum HashError {
+    PasswordTooLong,
+    UnknownFormat,
+    Argon2(String),
+    Bcrypt(BcryptError),
+}
+
+impl fmt::Display for HashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashError::PasswordTooLong =>
+                write!(f, "password exceeds {} bytes", BCRYPT_MAX_PASSWORD_BYTES),
+            HashError::UnknownFormat => write!(f, "unrecognized password hash format"),
+            HashError::Argon2(err) => write!(f, "argon2: {}", err),
+            HashError::Bcrypt(err) => write!(f, "bcrypt: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for HashError {}
+
+impl From<argon2::password_hash::Error> for HashError {
+    fn from(err: argon2::password_hash::Error) -> Self {
+        HashError::Argon2(err.to_string())
+    }
+}
+
+impl From<BcryptError> for HashError {
+    fn from(err: BcryptError) -> Self {
+        HashError::Bcrypt(err)
+    }
+}
+
+fn argon2(config: &PasswordHashConfig) -> Result<Argon2<'static>, HashError> {
+    let params = Params::new(
+        config.argon2_memory_kib,
+        config.argon2_iterations,
+        config.argon2_parallelism,
+        None
+    ).map_err(|err| HashError::Argon2(err.to_string()))?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| hash.starts_with(prefix))
+}
+
+// Hashes with the configured algorithm. Argon2id output is a PHC string
+// (`$argon2id$v=19$m=..,t=..,p=..$salt$hash`), bcrypt output is its usual `$2b$` string.
+pub fn hash(password: &str, config: &PasswordHashConfig) -> Result<String, HashError> {
+    match config.algorithm {
+        HashAlgorithm::Argon2id => {
+            let mut salt = [0u8; 16];
+            thread_rng().fill_bytes(&mut salt);
+            let salt = SaltString::encode_b64(&salt)?;
+            let hash = argon2(config)?.hash_password(password.as_bytes(), &salt)?;
+            Ok(hash.to_string())
+        }
+        HashAlgorithm::Bcrypt => {
+            if password.len() > BCRYPT_MAX_PASSWORD_BYTES {
+                return Err(HashError::PasswordTooLong);
+            }
+            Ok(bcrypt::hash_with_result(password, config.bcrypt_cost)?.to_string())
+        }
+    }
+}
+
+// Verifies against a hash made by any supported algorithm, using the parameters stored in it.
+pub fn verify(password: &str, hash: &str) -> Result<bool, HashError> {
+    if is_bcrypt(hash) {
+        return Ok(bcrypt::verify(password, hash)?);
+    }
+    let parsed = PasswordHash::new(hash).map_err(|_| HashError::UnknownFormat)?;
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// True when the hash was made with another algorithm or other parameters than the configured
+// ones, meaning it should be replaced the next time the plain password is available.
+pub fn needs_rehash(hash: &str, config: &PasswordHashConfig) -> bool {
+    match config.algorithm {
+        HashAlgorithm::Bcrypt =>
+            match hash.parse::<bcrypt::HashParts>() {
+                Ok(parts) => parts.get_cost() != config.bcrypt_cost,
+                Err(_) => true,
+            }
+        HashAlgorithm::Argon2id => {
+            let parsed = match PasswordHash::new(hash) {
+                Ok(parsed) => parsed,
+                Err(_) => {
+                    return true;
+                }
+            };
+            if parsed.algorithm != Algorithm::Argon2id.ident() {
+                return true;
+            }
+            match Params::try_from(&parsed) {
+                Ok(params) =>
+                    params.m_cost() != config.argon2_memory_kib ||
+                        params.t_cost() != config.argon2_iterations ||
+                        params.p_cost() != config.argon2_parallelism,
+                Err(_) => true,
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use serde::Serialize;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::config::config::PasswordPolicy;
+
+// Context fragments shorter than this are too common to be meaningful, e.g. "jo" in "jordan".
+const MIN_CONTEXT_LENGTH: usize = 3;
+
+// Passwords that show up at the top of every leaked list. They score 0 no matter how they look.
+const COMMON_PASSWORDS: [&str; 24] = [
+    "123456",
+    "password",
+    "12345678",
+    "qwerty",
+    "123456789",
+    "12345",
+    "1234",
+    "111111",
+    "1234567",
+    "dragon",
+    "123123",
+    "baseball",
+    "abc123",
+    "football",
+    "monkey",
+    "letmein",
+    "696969",
+    "shadow",
+    "master",
+    "666666",
+    "qwertyuiop",
+    "123321",
+    "mustang",
+    "password1",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "code", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PasswordViolation {
+    TooShort {
+        min: usize,
+    },
+    TooLong {
+        max: usize,
+    },
+    MissingLowercase,
+    MissingUppercase,
+    MissingDigit,
+    MissingSymbol,
+    TooWeak {
+        score: u8,
+        min: u8,
+    },
+    ContainsPersonalInfo,
+    Breached,
+}
+
+impl PasswordViolation {
+    pub fn message(&self) -> String {
+        match self {
+            PasswordViolation::TooShort { min } =>
+                format!("Password must be at least {} characters long.", min),
+            PasswordViolation::TooLong { max } =>
+                format!("Password must be at most {} characters long.", max),
+            PasswordViolation::MissingLowercase =>
+                "Password must contain at least one lowercase letter.".to_string(),
+            PasswordViolation::MissingUppercase =>
+                "Password must contain at least one uppercase letter.".to_string(),
+            PasswordViolation::MissingDigit =>
+                "Password must contain at least one number.".to_string(),
+            PasswordViolation::MissingSymbol =>
+                "Password must contain at least one special character.".to_string(),
+            PasswordViolation::TooWeak { .. } =>
+                "Password is too easy to guess. Try a longer or less predictable one.".to_string(),
+            PasswordViolation::ContainsPersonalInfo =>
+                "Password must not contain your name or email.".to_string(),
+            PasswordViolation::Breached =>
+                "This password appeared in a data breach. Please choose another one.".to_string(),
+        }
+    }
+}
+
+// Returns every rule the password breaks, so clients can show them all at once.
+// `context` holds user data the password must not contain, such as the name and email.
+pub fn validate(
+    password: &str,
+    policy: &PasswordPolicy,
+    context: &[&str]
+) -> Vec<PasswordViolation> {
+    let mut violations = Vec::new();
+
+    let length = password.graphemes(true).count();
+    if length < policy.min_length {
+        violations.push(PasswordViolation::TooShort { min: policy.min_length });
+    }
+    if length > policy.max_length {
+        violations.push(PasswordViolation::TooLong { max: policy.max_length });
+    }
+    if policy.require_lowercase && !password.chars().any(char::is_lowercase) {
+        violations.push(PasswordViolation::MissingLowercase);
+    }
+    if policy.require_uppercase && !password.chars().any(char::is_uppercase) {
+        violations.push(PasswordViolation::MissingUppercase);
+    }
+    if policy.require_digit && !password.chars().any(char::is_numeric) {
+        violations.push(PasswordViolation::MissingDigit);
+    }
+    if policy.require_symbol && !password.chars().any(is_symbol) {
+        violations.push(PasswordViolation::MissingSymbol);
+    }
+    if policy.min_strength > 0 {
+        let score = strength_score(password);
+        if score < policy.min_strength {
+            violations.push(PasswordViolation::TooWeak { score, min: policy.min_strength });
+        }
+    }
+    if policy.forbid_context && contains_context(password, context) {
+        violations.push(PasswordViolation::ContainsPersonalInfo);
+    }
+    if let Some(breached_passwords) = &policy.breached_passwords {
+        if breached_passwords.contains(password) {
+            violations.push(PasswordViolation::Breached);
+        }
+    }
+
+    violations
+}
+
+fn is_symbol(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+}
+
+fn contains_context(password: &str, context: &[&str]) -> bool {
+    let password = password.to_lowercase();
+    context
+        .iter()
+        .flat_map(|value| value.split(|c: char| !c.is_alphanumeric()))
+        .map(|fragment| fragment.to_lowercase())
+        .filter(|fragment| fragment.chars().count() >= MIN_CONTEXT_LENGTH)
+        .any(|fragment| password.contains(&fragment))
+}
+
+// Estimates how many guesses an attacker needs and buckets it into a 0-4 score, using the same
+// thresholds as zxcvbn. The estimate is a brute-force search over the character classes used,
+// where repeated and sequential characters ("aaa", "abc", "321") are discounted.
+pub fn strength_score(password: &str) -> u8 {
+    let lowered = password.to_lowercase();
+    if COMMON_PASSWORDS.contains(&lowered.as_str()) {
+        return 0;
+    }
+
+    let chars: Vec<char> = password.chars().collect();
+    let mut pool = 0u32;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if chars.iter().any(|c| c.is_ascii() && is_symbol(*c)) {
+        pool += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += 100;
+    }
+    if pool == 0 {
+        return 0;
+    }
+
+    // Characters continuing a run of repeats or a +1/-1 sequence add half as much entropy.
+    let mut effective_length = 0.0;
+    for (i, c) in chars.iter().enumerate() {
+        let predictable = i > 0 && ((*c as i64) - (chars[i - 1] as i64)).abs() <= 1;
+        effective_length += if predictable { 0.5 } else { 1.0 };
+    }
+
+    let log10_guesses = effective_length * (pool as f64).log10();
+    match log10_guesses {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::source::ConfigSource;
+    use super::*;
+
+    fn policy(settings: &[(&str, &str)]) -> PasswordPolicy {
+        PasswordPolicy::init(&ConfigSource::from_values(settings))
+    }
+
+    // The `code` each violation is reported with.
+    fn codes(violations: &[PasswordViolation]) -> Vec<String> {
+        violations
+            .iter()
+            .map(|violation| serde_json::to_value(violation).unwrap()["code"].to_string())
+            .map(|code| code.trim_matches('"').to_string())
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_password_meeting_the_defaults() {
+        assert!(validate("Str0ng!pass", &policy(&[]), &["Ann", "ann"]).is_empty());
+    }
+
+    #[test]
+    fn lists_every_violation() {
+        let policy = policy(&[
+            ("PASSWORD_MIN_LENGTH", "8"),
+            ("PASSWORD_REQUIRE_UPPERCASE", "true"),
+            ("PASSWORD_REQUIRE_LOWERCASE", "true"),
+        ]);
+        let violations = validate("abc", &policy, &[]);
+        assert_eq!(
+            codes(&violations),
+            ["TOO_SHORT", "MISSING_UPPERCASE", "MISSING_DIGIT", "MISSING_SYMBOL"]
+        );
+    }
+
+    #[test]
+    fn counts_length_in_graphemes() {
+        let policy = policy(&[
+            ("PASSWORD_MAX_LENGTH", "4"),
+            ("PASSWORD_REQUIRE_DIGIT", "false"),
+            ("PASSWORD_REQUIRE_SYMBOL", "false"),
+            ("PASSWORD_MIN_LENGTH", "1"),
+        ]);
+        // Four family emojis, each several code points long.
+        assert!(validate("👨‍👩‍👧👨‍👩‍👧👨‍👩‍👧👨‍👩‍👧", &policy, &[]).is_empty());
+        assert_eq!(codes(&validate("abcde", &policy, &[])), ["TOO_LONG"]);
+    }
+
+    #[test]
+    fn rejects_personal_info() {
+        let violations = validate("Jordan!2024", &policy(&[]), &["jordan@example.com", "J"]);
+        assert_eq!(codes(&violations), ["CONTAINS_PERSONAL_INFO"]);
+        let policy = policy(&[("PASSWORD_FORBID_CONTEXT", "false")]);
+        assert!(validate("Jordan!2024", &policy, &["jordan@example.com"]).is_empty());
+    }
+
+    #[test]
+    fn scores_strength() {
+        assert_eq!(strength_score("password1"), 0);
+        assert_eq!(strength_score("abcdef"), 1);
+        assert!(strength_score("Str0ng!pass") >= 3);
+        assert_eq!(strength_score("correct-Horse-battery-st4ple"), 4);
+
+        let policy = policy(&[("PASSWORD_MIN_STRENGTH", "4")]);
+        assert_eq!(codes(&validate("abc123!", &policy, &[])), ["TOO_WEAK"]);
+        assert!(validate("Str0ng!pass", &policy, &[]).is_empty());
+    }
+}